@@ -0,0 +1,484 @@
+//! What a collection of parts can build.
+use crate::input::{merge_inventories, Catalogue, Error, InventoryPart, Result, Set, Theme};
+use std::collections::{HashMap, HashSet};
+
+/// Which parts of the collection may stand in for the parts of the set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Substitutes {
+    /// Use the same part in another color.
+    pub colors: bool,
+    /// Use alternates, other molds, or the unprinted part instead of a print.
+    pub equivalent_parts: bool,
+}
+
+/// A part of the set replaced by another part or color of the collection.
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub part_num: String,
+    pub color_id: i32,
+    pub quantity: i32,
+    pub used_part_num: String,
+    pub used_color_id: i32,
+}
+
+/// How much of an inventory a collection covers.
+#[derive(Debug, Clone)]
+pub struct Buildability {
+    /// Number of parts of the inventory.
+    pub required: i32,
+    /// Number of parts of the inventory the collection has, including substitutes.
+    pub covered: i32,
+    /// Parts the collection lacks, with the quantities still needed.
+    pub missing: Vec<InventoryPart>,
+    pub substitutions: Vec<Substitution>,
+}
+
+impl Buildability {
+    /// Covered parts in percent, 100 for an empty inventory.
+    pub fn completion(&self) -> f32 {
+        if self.required == 0 {
+            return 100.0;
+        }
+        self.covered as f32 / self.required as f32 * 100.0
+    }
+}
+
+/// Parts of a collection indexed by part and color, to check many inventories against.
+#[derive(Debug, Clone, Default)]
+pub struct Collection {
+    quantities: HashMap<String, HashMap<i32, i32>>,
+}
+
+impl Collection {
+    /// Indexes the parts, spare parts of the collection count like any other part.
+    pub fn new(collection: &[InventoryPart]) -> Collection {
+        let mut quantities: HashMap<String, HashMap<i32, i32>> = HashMap::new();
+        for inventory_part in collection {
+            *quantities
+                .entry(inventory_part.part_num.clone())
+                .or_default()
+                .entry(inventory_part.color_id)
+                .or_default() += inventory_part.quantity;
+        }
+        Collection { quantities }
+    }
+
+    fn quantity(&self, part_num: &str, color_id: i32) -> i32 {
+        self.quantities
+            .get(part_num)
+            .and_then(|colors| colors.get(&color_id))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// A set scored by how much of it a collection covers.
+#[derive(Debug, Clone)]
+pub struct RankedSet {
+    pub set: Set,
+    pub buildability: Buildability,
+}
+
+impl RankedSet {
+    pub fn missing_parts(&self) -> i32 {
+        self.buildability.required - self.buildability.covered
+    }
+}
+
+/// Which sets to rank, all sets with parts by default.
+#[derive(Debug, Clone, Default)]
+pub struct SetFilter {
+    /// Theme id or name, compared ignoring case, including its sub-themes.
+    pub theme: Option<String>,
+    /// First and last year of release.
+    pub years: Option<(i32, i32)>,
+}
+
+/// The sets with parts that the filter accepts.
+///
+/// A theme name can belong to several themes, e.g. sub-themes of the same name, sets of all
+/// of them are kept. An unknown theme is not found.
+pub fn filter_sets(sets: Vec<Set>, themes: &[Theme], filter: &SetFilter) -> Result<Vec<Set>> {
+    let theme_ids: Option<HashSet<i32>> = match &filter.theme {
+        Some(theme) => {
+            let matching: Vec<i32> = themes
+                .iter()
+                .filter(|candidate| {
+                    candidate.id.to_string() == *theme || candidate.name.eq_ignore_ascii_case(theme)
+                })
+                .map(|candidate| candidate.id)
+                .collect();
+            if matching.is_empty() {
+                return Err(Error::NotFound {
+                    url: format!("theme {}", theme),
+                });
+            }
+            Some(
+                matching
+                    .into_iter()
+                    .flat_map(|theme_id| theme_with_subthemes(themes, theme_id))
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    Ok(sets
+        .into_iter()
+        .filter(|set| set.num_parts > 0)
+        .filter(|set| {
+            theme_ids
+                .as_ref()
+                .is_none_or(|theme_ids| theme_ids.contains(&set.theme_id))
+        })
+        .filter(|set| {
+            filter
+                .years
+                .is_none_or(|(from, to)| (from..=to).contains(&set.year))
+        })
+        .collect())
+}
+
+/// Scores every set by the covered fraction of its parts, the best first.
+///
+/// Sets without inventory are skipped. Equal completions rank larger sets first.
+pub fn rank(
+    sets: Vec<Set>,
+    inventories: &HashMap<String, Vec<InventoryPart>>,
+    collection: &[InventoryPart],
+    catalogue: &Catalogue,
+    substitutes: Substitutes,
+) -> Vec<RankedSet> {
+    let collection = Collection::new(collection);
+    let mut ranking: Vec<RankedSet> = sets
+        .into_iter()
+        .filter_map(|set| {
+            let inventory_parts = inventories.get(&set.set_num)?;
+            if inventory_parts.is_empty() {
+                return None;
+            }
+            let buildability = check(inventory_parts, &collection, catalogue, substitutes);
+            Some(RankedSet { set, buildability })
+        })
+        .collect();
+    ranking.sort_by(|a, b| {
+        b.buildability
+            .completion()
+            .total_cmp(&a.buildability.completion())
+            .then(b.buildability.required.cmp(&a.buildability.required))
+            .then_with(|| a.set.set_num.cmp(&b.set.set_num))
+    });
+    ranking
+}
+
+/// Ids of the theme and all themes nested in it.
+pub fn theme_with_subthemes(themes: &[Theme], theme_id: i32) -> HashSet<i32> {
+    let mut theme_ids = HashSet::from([theme_id]);
+    // add children until no new theme is found, the nesting is only a few levels deep
+    loop {
+        let children: Vec<i32> = themes
+            .iter()
+            .filter(|theme| {
+                theme
+                    .parent_id
+                    .is_some_and(|parent_id| theme_ids.contains(&parent_id))
+                    && !theme_ids.contains(&theme.id)
+            })
+            .map(|theme| theme.id)
+            .collect();
+        if children.is_empty() {
+            return theme_ids;
+        }
+        theme_ids.extend(children);
+    }
+}
+
+/// Checks which parts of the inventory the collection covers.
+///
+/// Exact matches are used first for all parts, substitutes only for what is still missing,
+/// so that substitutes never take parts another lot needs exactly. Spare parts of the
+/// inventory are not needed, those of the collection count like any other part.
+/// `catalogue` needs the details of the inventory's parts to find equivalent parts.
+pub fn check(
+    inventory_parts: &[InventoryPart],
+    collection: &Collection,
+    catalogue: &Catalogue,
+    substitutes: Substitutes,
+) -> Buildability {
+    let mut available = Available {
+        collection,
+        taken: HashMap::new(),
+    };
+
+    let needed_parts: Vec<InventoryPart> = inventory_parts
+        .iter()
+        .filter(|inventory_part| !inventory_part.is_spare)
+        .cloned()
+        .collect();
+    let mut needed = merge_inventories([(1, needed_parts)]);
+    let required = needed
+        .iter()
+        .map(|inventory_part| inventory_part.quantity)
+        .sum();
+    let mut substitutions = Vec::new();
+
+    // exact matches
+    for inventory_part in &mut needed {
+        let key = (inventory_part.part_num.clone(), inventory_part.color_id);
+        available.take(&key, &mut inventory_part.quantity);
+    }
+
+    // substitutes, the closest ones first
+    if substitutes.equivalent_parts {
+        for inventory_part in &mut needed {
+            for part_num in equivalent_parts(&inventory_part.part_num, catalogue) {
+                let key = (part_num, inventory_part.color_id);
+                substitute(&mut available, &key, inventory_part, &mut substitutions);
+            }
+        }
+    }
+    if substitutes.colors {
+        for inventory_part in &mut needed {
+            let mut part_nums = vec![inventory_part.part_num.clone()];
+            if substitutes.equivalent_parts {
+                part_nums.extend(equivalent_parts(&inventory_part.part_num, catalogue));
+            }
+            for part_num in part_nums {
+                for key in available.colors_by_availability(&part_num) {
+                    substitute(&mut available, &key, inventory_part, &mut substitutions);
+                }
+            }
+        }
+    }
+
+    let missing: Vec<InventoryPart> = needed
+        .into_iter()
+        .filter(|inventory_part| inventory_part.quantity > 0)
+        .collect();
+    let missing_count: i32 = missing
+        .iter()
+        .map(|inventory_part| inventory_part.quantity)
+        .sum();
+    Buildability {
+        required,
+        covered: required - missing_count,
+        missing,
+        substitutions,
+    }
+}
+
+// the parts of the collection not yet used for the inventory being checked,
+// only what is taken is tracked so that the collection is not copied for every inventory
+struct Available<'a> {
+    collection: &'a Collection,
+    taken: HashMap<(String, i32), i32>,
+}
+
+impl Available<'_> {
+    fn quantity(&self, key: &(String, i32)) -> i32 {
+        self.collection.quantity(&key.0, key.1) - self.taken.get(key).copied().unwrap_or(0)
+    }
+
+    // take as many as possible of the needed quantity from the available parts
+    fn take(&mut self, key: &(String, i32), needed: &mut i32) -> i32 {
+        let taken = self.quantity(key).min(*needed);
+        if taken <= 0 {
+            return 0;
+        }
+        *self.taken.entry(key.clone()).or_default() += taken;
+        *needed -= taken;
+        taken
+    }
+
+    // available colors of the part, the most plentiful first
+    fn colors_by_availability(&self, part_num: &str) -> Vec<(String, i32)> {
+        let colors = match self.collection.quantities.get(part_num) {
+            Some(colors) => colors,
+            None => return Vec::new(),
+        };
+        let mut colors: Vec<(i32, i32)> = colors
+            .keys()
+            .map(|color_id| {
+                let key = (part_num.to_string(), *color_id);
+                (*color_id, self.quantity(&key))
+            })
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect();
+        colors.sort_by_key(|(color_id, quantity)| (std::cmp::Reverse(*quantity), *color_id));
+        colors
+            .into_iter()
+            .map(|(color_id, _)| (part_num.to_string(), color_id))
+            .collect()
+    }
+}
+
+fn substitute(
+    available: &mut Available,
+    key: &(String, i32),
+    inventory_part: &mut InventoryPart,
+    substitutions: &mut Vec<Substitution>,
+) {
+    let part_num = inventory_part.part_num.clone();
+    let taken = available.take(key, &mut inventory_part.quantity);
+    if taken > 0 {
+        substitutions.push(Substitution {
+            part_num,
+            color_id: inventory_part.color_id,
+            quantity: taken,
+            used_part_num: key.0.clone(),
+            used_color_id: key.1,
+        });
+    }
+}
+
+// alternates and other molds of the part, and the unprinted part if it is a print
+fn equivalent_parts(part_num: &str, catalogue: &Catalogue) -> Vec<String> {
+    let part = match catalogue.part(part_num) {
+        Some(part) => part,
+        None => return Vec::new(),
+    };
+    let mut part_nums: Vec<String> = part
+        .alternates
+        .iter()
+        .chain(&part.molds)
+        .chain(&part.print_of)
+        .filter(|other| other.as_str() != part_num)
+        .cloned()
+        .collect();
+    part_nums.dedup();
+    part_nums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // brick 3001 with another mold 3001old
+    fn catalogue() -> Catalogue {
+        let parts = serde_json::from_value(json!([
+            {"part_num": "3001", "name": "Brick 2 x 4", "part_cat_id": 11, "molds": ["3001old"]},
+            {"part_num": "3001old", "name": "Brick 2 x 4 old", "part_cat_id": 11, "molds": ["3001"]}
+        ]))
+        .unwrap();
+        Catalogue::new(parts, Vec::new(), Vec::new())
+    }
+
+    fn part(part_num: &str, color_id: i32, quantity: i32) -> InventoryPart {
+        InventoryPart::new(part_num.to_string(), color_id, quantity, false)
+    }
+
+    #[test]
+    fn sets_are_filtered_by_theme_with_subthemes_and_years() {
+        let themes: Vec<Theme> = serde_json::from_value(json!([
+            {"id": 1, "name": "Castle", "parent_id": null},
+            {"id": 2, "name": "Black Falcons", "parent_id": 1},
+            {"id": 3, "name": "Space", "parent_id": null}
+        ]))
+        .unwrap();
+        let sets: Vec<Set> = serde_json::from_value(json!([
+            {"set_num": "6073-1", "name": "Knight's Castle", "year": 1984, "theme_id": 1, "num_parts": 410},
+            {"set_num": "6080-1", "name": "King's Castle", "year": 1988, "theme_id": 2, "num_parts": 673},
+            {"set_num": "6980-1", "name": "Galaxy Commander", "year": 1983, "theme_id": 3, "num_parts": 380},
+            {"set_num": "1000-1", "name": "Gear", "year": 1985, "theme_id": 1, "num_parts": 0}
+        ]))
+        .unwrap();
+        let set_nums = |filter: SetFilter| -> Vec<String> {
+            filter_sets(sets.clone(), &themes, &filter)
+                .unwrap()
+                .into_iter()
+                .map(|set| set.set_num)
+                .collect()
+        };
+
+        assert_eq!(set_nums(SetFilter::default()).len(), 3);
+        let castle = SetFilter {
+            theme: Some("castle".to_string()),
+            years: None,
+        };
+        assert_eq!(set_nums(castle), vec!["6073-1", "6080-1"]);
+        let castle_in_1984 = SetFilter {
+            theme: Some("1".to_string()),
+            years: Some((1984, 1984)),
+        };
+        assert_eq!(set_nums(castle_in_1984), vec!["6073-1"]);
+        let unknown = SetFilter {
+            theme: Some("Pirates".to_string()),
+            years: None,
+        };
+        assert!(matches!(
+            filter_sets(sets.clone(), &themes, &unknown),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn exact_matches_are_used_before_substitutes() {
+        let inventory = [part("3001", 4, 2), part("3001", 1, 1)];
+        let collection = Collection::new(&[part("3001", 4, 1), part("3001", 1, 2)]);
+        let substitutes = Substitutes {
+            colors: true,
+            equivalent_parts: false,
+        };
+        let buildability = check(&inventory, &collection, &catalogue(), substitutes);
+        assert_eq!(buildability.required, 3);
+        assert_eq!(buildability.covered, 3);
+        assert!(buildability.missing.is_empty());
+        // only the blue brick left over stands in for the second red one
+        assert_eq!(buildability.substitutions.len(), 1);
+        let substitution = &buildability.substitutions[0];
+        assert_eq!((substitution.color_id, substitution.quantity), (4, 1));
+        assert_eq!(
+            (
+                substitution.used_part_num.as_str(),
+                substitution.used_color_id
+            ),
+            ("3001", 1)
+        );
+        // checking leaves the collection as it is for the next inventory
+        let again = check(&inventory, &collection, &catalogue(), substitutes);
+        assert_eq!(again.covered, 3);
+        assert_eq!(again.substitutions.len(), 1);
+    }
+
+    #[test]
+    fn equivalent_parts_are_used_before_other_colors() {
+        let inventory = [part("3001", 4, 1)];
+        let collection = Collection::new(&[part("3001", 1, 1), part("3001old", 4, 1)]);
+        let substitutes = Substitutes {
+            colors: true,
+            equivalent_parts: true,
+        };
+        let buildability = check(&inventory, &collection, &catalogue(), substitutes);
+        assert_eq!(buildability.covered, 1);
+        assert_eq!(buildability.substitutions.len(), 1);
+        let substitution = &buildability.substitutions[0];
+        assert_eq!(
+            (
+                substitution.used_part_num.as_str(),
+                substitution.used_color_id
+            ),
+            ("3001old", 4)
+        );
+    }
+
+    #[test]
+    fn spares_are_not_needed_and_substitutes_only_when_allowed() {
+        let inventory = [
+            part("3001", 4, 2),
+            InventoryPart::new("3001".to_string(), 4, 1, true),
+        ];
+        let collection = Collection::new(&[part("3001", 1, 5)]);
+        let buildability = check(
+            &inventory,
+            &collection,
+            &catalogue(),
+            Substitutes::default(),
+        );
+        assert_eq!(buildability.required, 2);
+        assert_eq!(buildability.covered, 0);
+        assert_eq!(buildability.completion(), 0.0);
+        assert!(buildability.substitutions.is_empty());
+        assert_eq!(buildability.missing.len(), 1);
+        assert_eq!(buildability.missing[0].quantity, 2);
+    }
+}
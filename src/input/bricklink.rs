@@ -0,0 +1,287 @@
+//! BrickLink wanted lists and inventories in xml format
+//! <https://www.bricklink.com/help.asp?helpID=207>
+use super::error::{Error, Result};
+use super::{merge_inventories, Catalogue, Color, Database, InventoryPart};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+
+// name of bricklink in the external ids of parts and colors
+const CATALOG: &str = "BrickLink";
+
+// an ITEM of the list, with bricklink ids
+struct Item {
+    item_type: String,
+    item_id: String,
+    color: i32,
+    quantity: i32,
+}
+
+// read all ITEMs of an INVENTORY
+fn read_items(path: &str) -> Result<Vec<Item>> {
+    let text = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let invalid = |message: String| Error::InvalidFile {
+        path: path.to_string(),
+        message,
+    };
+    let document = roxmltree::Document::parse(&text).map_err(|e| invalid(e.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("INVENTORY") {
+        return Err(invalid(format!(
+            "expected INVENTORY, found {}",
+            root.tag_name().name()
+        )));
+    }
+
+    let mut items = Vec::new();
+    for (index, node) in root
+        .children()
+        .filter(|node| node.has_tag_name("ITEM"))
+        .enumerate()
+    {
+        let field = |name: &str| {
+            node.children()
+                .find(|child| child.has_tag_name(name))
+                .and_then(|child| child.text())
+                .map(|text| text.trim().to_string())
+        };
+        let number = |name: &str, default: i32| match field(name) {
+            Some(text) => text
+                .parse::<i32>()
+                .map_err(|_| invalid(format!("item {}: invalid {} {}", index + 1, name, text))),
+            None => Ok(default),
+        };
+        let item_id = field("ITEMID")
+            .ok_or_else(|| invalid(format!("item {}: ITEMID missing", index + 1)))?;
+        // wanted lists have MINQTY, inventories QTY, both default to one
+        let quantity = match field("MINQTY") {
+            Some(_) => number("MINQTY", 1)?,
+            None => number("QTY", 1)?,
+        };
+        items.push(Item {
+            item_type: field("ITEMTYPE").unwrap_or_else(|| "P".to_string()),
+            item_id,
+            color: number("COLOR", 0)?,
+            quantity,
+        });
+    }
+    Ok(items)
+}
+
+/// Reads a bricklink wanted list or inventory in .xml format, mapping the bricklink part and
+/// color ids to rebrickable ones. Items other than parts and parts in unknown colors are skipped.
+pub fn inventory_from_bricklink_file(
+    path: &str,
+    database: &dyn Database,
+) -> Result<Vec<InventoryPart>> {
+    let (items, others): (Vec<Item>, Vec<Item>) = read_items(path)?
+        .into_iter()
+        .partition(|item| item.item_type == "P");
+    for item in others {
+        println!(
+            "Warning: skipping item {} of type {} in {}, only parts are supported",
+            item.item_id, item.item_type, path
+        );
+    }
+
+    let mut ext_ids: Vec<String> = items.iter().map(|item| item.item_id.clone()).collect();
+    ext_ids.sort();
+    ext_ids.dedup();
+    let part_nums = database.part_nums_by_external_id(CATALOG, &ext_ids)?;
+    for ext_id in ext_ids
+        .iter()
+        .filter(|ext_id| !part_nums.contains_key(*ext_id))
+    {
+        println!(
+            "Warning: no rebrickable part for bricklink part {}, keeping its number",
+            ext_id
+        );
+    }
+
+    // several rebrickable colors can share a bricklink color, the lowest id wins
+    let mut colors = database.all_colors()?;
+    colors.sort_by_key(|color| color.id);
+    let mut color_ids: HashMap<i32, i32> = HashMap::new();
+    for color in &colors {
+        for ext_id in bricklink_color_ids(color) {
+            color_ids.entry(ext_id).or_insert(color.id);
+        }
+    }
+    if color_ids.is_empty() && !items.is_empty() {
+        return Err(no_color_ids());
+    }
+
+    let mut inventory_parts = Vec::new();
+    for item in items {
+        let color_id = match color_ids.get(&item.color) {
+            Some(color_id) => *color_id,
+            None => {
+                println!(
+                    "Warning: skipping part {} in unknown bricklink color {}",
+                    item.item_id, item.color
+                );
+                continue;
+            }
+        };
+        let part_num = part_nums
+            .get(&item.item_id)
+            .cloned()
+            .unwrap_or(item.item_id);
+        inventory_parts.push(InventoryPart::new(part_num, color_id, item.quantity, false));
+    }
+    Ok(merge_inventories([(1, inventory_parts)]))
+}
+
+/// Writes the inventory as bricklink wanted list in .xml format, mapping part and color ids
+/// with the external ids in `catalogue`. Parts without bricklink id keep their number,
+/// parts in colors without bricklink id are skipped. Parts that map to the same bricklink
+/// part and color, e.g. spare and regular parts, are written as one item.
+pub fn inventory_to_bricklink_file(
+    inventory_parts: &[InventoryPart],
+    catalogue: &Catalogue,
+    path: &str,
+) -> Result<()> {
+    if !catalogue
+        .colors()
+        .any(|color| bricklink_color_ids(color).next().is_some())
+    {
+        return Err(no_color_ids());
+    }
+
+    // bricklink part, color and quantity in the order of the inventory
+    let mut items: Vec<(String, i32, i32)> = Vec::new();
+    let mut item_indices: HashMap<(String, i32), usize> = HashMap::new();
+    for inventory_part in inventory_parts {
+        let color = match catalogue
+            .color(inventory_part.color_id)
+            .and_then(|color| bricklink_color_ids(color).next())
+        {
+            Some(color) => color,
+            None => {
+                println!(
+                    "Warning: skipping part {} in color {} without bricklink id",
+                    inventory_part.part_num, inventory_part.color_id
+                );
+                continue;
+            }
+        };
+        let item_id = catalogue
+            .part(&inventory_part.part_num)
+            .and_then(|part| part.external_ids.get(CATALOG))
+            .and_then(|ext_ids| ext_ids.first())
+            .unwrap_or(&inventory_part.part_num)
+            .clone();
+        match item_indices.entry((item_id.clone(), color)) {
+            Entry::Occupied(entry) => items[*entry.get()].2 += inventory_part.quantity,
+            Entry::Vacant(entry) => {
+                entry.insert(items.len());
+                items.push((item_id, color, inventory_part.quantity));
+            }
+        }
+    }
+
+    let mut xml = String::from("<INVENTORY>\n");
+    for (item_id, color, quantity) in items {
+        xml.push_str("  <ITEM>\n");
+        xml.push_str("    <ITEMTYPE>P</ITEMTYPE>\n");
+        xml.push_str(&format!("    <ITEMID>{}</ITEMID>\n", escape(&item_id)));
+        xml.push_str(&format!("    <COLOR>{}</COLOR>\n", color));
+        xml.push_str(&format!("    <MINQTY>{}</MINQTY>\n", quantity));
+        xml.push_str("  </ITEM>\n");
+    }
+    xml.push_str("</INVENTORY>\n");
+    fs::write(path, xml).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+// bricklink ids of the color, usually exactly one
+fn bricklink_color_ids(color: &Color) -> impl Iterator<Item = i32> + '_ {
+    color
+        .external_ids
+        .get(CATALOG)
+        .into_iter()
+        .flat_map(|ext_ids| ext_ids.ext_ids.iter().flatten().copied())
+}
+
+// without any bricklink color id every part would be skipped
+fn no_color_ids() -> Error {
+    Error::Unsupported(
+        "the colors have no bricklink ids, the database dumps have none, use the api".to_string(),
+    )
+}
+
+// escape text for xml elements
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // red and a second rebrickable red mapped to bricklink color 5, or colors without ids
+    fn catalogue(with_ids: bool) -> Catalogue {
+        let external_ids = |id: i32| {
+            if with_ids {
+                json!({"BrickLink": {"ext_ids": [id], "ext_descrs": [["Red"]]}})
+            } else {
+                json!({})
+            }
+        };
+        let colors = serde_json::from_value(json!([
+            {"id": 4, "name": "Red", "rgb": "C91A09", "is_trans": false, "external_ids": external_ids(5)},
+            {"id": 1004, "name": "Red Too", "rgb": "C91A09", "is_trans": false, "external_ids": external_ids(5)}
+        ]))
+        .unwrap();
+        let parts = serde_json::from_value(json!([
+            {"part_num": "3001", "name": "Brick 2 x 4", "part_cat_id": 11, "external_ids": {"BrickLink": ["3001b"]}}
+        ]))
+        .unwrap();
+        Catalogue::new(parts, colors, Vec::new())
+    }
+
+    fn path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "brickstats-bricklink-{}-{}.xml",
+                name,
+                std::process::id()
+            ))
+            .display()
+            .to_string()
+    }
+
+    #[test]
+    fn items_of_the_same_bricklink_part_and_color_are_merged() {
+        let inventory = [
+            InventoryPart::new("3001".to_string(), 4, 2, false),
+            InventoryPart::new("3001".to_string(), 4, 1, true),
+            InventoryPart::new("3001".to_string(), 1004, 3, false),
+        ];
+        let path = path("merged");
+        inventory_to_bricklink_file(&inventory, &catalogue(true), &path).unwrap();
+        let xml = fs::read_to_string(&path).unwrap();
+        assert_eq!(xml.matches("<ITEM>").count(), 1);
+        assert!(xml.contains("<ITEMID>3001b</ITEMID>"));
+        assert!(xml.contains("<COLOR>5</COLOR>"));
+        assert!(xml.contains("<MINQTY>6</MINQTY>"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn colors_without_bricklink_ids_are_unsupported() {
+        let inventory = [InventoryPart::new("3001".to_string(), 4, 2, false)];
+        let path = path("dump");
+        let result = inventory_to_bricklink_file(&inventory, &catalogue(false), &path);
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        assert!(!std::path::Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,368 @@
+//! On-disk cache for everything fetched from the rebrickable api.
+use super::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const CACHE_DIRECTORY: &str = "data/cache";
+pub const DEFAULT_TTL_DAYS: u64 = 30;
+
+/// How the cache is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Use entries younger than the ttl, fetch everything else.
+    Normal,
+    /// Ignore cached entries and fetch everything again.
+    Refresh,
+    /// Never fetch, use cached entries regardless of their age.
+    Offline,
+    /// Neither read nor write the cache, e.g. while recording or replaying fixtures.
+    Disabled,
+}
+
+// a cached value with the time it was fetched
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry<T> {
+    fetched_at: u64,
+    data: T,
+}
+
+/// Number and size of the cached entries of one endpoint.
+#[derive(Debug, Clone)]
+pub struct CacheUsage {
+    pub endpoint: String,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Cached api responses, one json file per endpoint and key with the time it was fetched.
+pub struct Cache {
+    directory: PathBuf,
+    ttl: Duration,
+    mode: CacheMode,
+}
+
+impl Cache {
+    pub fn new(directory: &str, ttl: Duration, mode: CacheMode) -> Cache {
+        Cache {
+            directory: PathBuf::from(directory),
+            ttl,
+            mode,
+        }
+    }
+
+    /// Gets the value for endpoint and key from the cache or fetches and stores it.
+    pub fn get_or_fetch<T, F>(&self, endpoint: &str, key: &str, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        if self.mode == CacheMode::Disabled {
+            return fetch();
+        }
+        if let Some(data) = self.get(endpoint, key)? {
+            return Ok(data);
+        }
+        if self.mode == CacheMode::Offline {
+            return Err(not_cached(endpoint, key));
+        }
+        let data = fetch()?;
+        self.put(endpoint, key, &data)?;
+        Ok(data)
+    }
+
+    /// Fetches the value and stores it, for data that changes often like user collections.
+    /// Only offline mode answers from the cache.
+    pub fn fetch_and_store<T, F>(&self, endpoint: &str, key: &str, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        match self.mode {
+            CacheMode::Disabled => fetch(),
+            CacheMode::Offline => self
+                .get(endpoint, key)?
+                .ok_or_else(|| not_cached(endpoint, key)),
+            CacheMode::Normal | CacheMode::Refresh => {
+                let data = fetch()?;
+                self.put(endpoint, key, &data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Like [`Cache::get_or_fetch`] for many keys at once, only keys missing from the cache
+    /// are fetched. Values the fetch does not return are left out of the result and cached
+    /// as absent, so offline runs know them as well.
+    pub fn get_many_or_fetch<T, F, K>(
+        &self,
+        endpoint: &str,
+        keys: &[String],
+        fetch: F,
+        key_of: K,
+    ) -> Result<Vec<T>>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(&[String]) -> Result<Vec<T>>,
+        K: Fn(&T) -> String,
+    {
+        if self.mode == CacheMode::Disabled {
+            return fetch(keys);
+        }
+        let mut values = Vec::new();
+        let mut missing = Vec::new();
+        for key in keys {
+            // absent values, e.g. unknown part numbers, are cached as null
+            match self.get::<Option<T>>(endpoint, key)? {
+                Some(Some(data)) => values.push(data),
+                Some(None) => {}
+                None => missing.push(key.clone()),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(values);
+        }
+        if self.mode == CacheMode::Offline {
+            return Err(not_cached(endpoint, &missing.join(",")));
+        }
+        println!(
+            "{} of {} {} cached, fetching {}",
+            keys.len() - missing.len(),
+            keys.len(),
+            endpoint,
+            missing.len()
+        );
+        let mut fetched = HashSet::new();
+        for data in fetch(&missing)? {
+            let key = key_of(&data);
+            self.put(endpoint, &key, &data)?;
+            fetched.insert(key);
+            values.push(data);
+        }
+        for key in missing.iter().filter(|key| !fetched.contains(*key)) {
+            self.put(endpoint, key, &None::<T>)?;
+        }
+        Ok(values)
+    }
+
+    /// Number and size of the cached entries per endpoint, sorted by endpoint.
+    pub fn usage(&self) -> Result<Vec<CacheUsage>> {
+        let mut usage = Vec::new();
+        for endpoint in self.endpoints()? {
+            let mut entries = 0;
+            let mut bytes = 0;
+            for path in entry_files(&self.directory.join(&endpoint))? {
+                let metadata = fs::metadata(&path).map_err(|source| io_error(&path, source))?;
+                entries += 1;
+                bytes += metadata.len();
+            }
+            usage.push(CacheUsage {
+                endpoint,
+                entries,
+                bytes,
+            });
+        }
+        Ok(usage)
+    }
+
+    /// Removes all cached entries of an endpoint, or of all endpoints if none is given.
+    /// Returns the number of removed entries.
+    pub fn clear(&self, endpoint: Option<&str>) -> Result<usize> {
+        let endpoints = match endpoint {
+            Some(endpoint) => vec![endpoint.to_string()],
+            None => self.endpoints()?,
+        };
+        let mut removed = 0;
+        for endpoint in endpoints {
+            let directory = self.directory.join(&endpoint);
+            if !directory.is_dir() {
+                continue;
+            }
+            removed += entry_files(&directory)?.len();
+            fs::remove_dir_all(&directory).map_err(|source| io_error(&directory, source))?;
+        }
+        Ok(removed)
+    }
+
+    // one directory per endpoint
+    fn endpoints(&self) -> Result<Vec<String>> {
+        if !self.directory.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut endpoints = Vec::new();
+        for entry in read_dir(&self.directory)? {
+            if entry.is_dir() {
+                if let Some(name) = entry.file_name().and_then(|name| name.to_str()) {
+                    endpoints.push(name.to_string());
+                }
+            }
+        }
+        endpoints.sort();
+        Ok(endpoints)
+    }
+
+    fn get<T: DeserializeOwned>(&self, endpoint: &str, key: &str) -> Result<Option<T>> {
+        if self.mode == CacheMode::Refresh {
+            return Ok(None);
+        }
+        let path = self.path(endpoint, key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|source| io_error(&path, source))?;
+        let entry: Entry<T> = match serde_json::from_str(&text) {
+            Ok(entry) => entry,
+            // entries written by an older version are fetched again
+            Err(_) => {
+                println!(
+                    "Warning: ignoring unreadable cache entry {}",
+                    path.display()
+                );
+                return Ok(None);
+            }
+        };
+        let age = now().saturating_sub(entry.fetched_at);
+        if self.mode != CacheMode::Offline && age > self.ttl.as_secs() {
+            return Ok(None);
+        }
+        Ok(Some(entry.data))
+    }
+
+    fn put<T: Serialize>(&self, endpoint: &str, key: &str, data: &T) -> Result<()> {
+        let path = self.path(endpoint, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|source| io_error(parent, source))?;
+        }
+        let entry = Entry {
+            fetched_at: now(),
+            data,
+        };
+        let text = serde_json::to_string(&entry).expect("cache entries are always serializable");
+        fs::write(&path, text).map_err(|source| io_error(&path, source))
+    }
+
+    // one file per entry: {directory}/{endpoint}/{key}.json
+    fn path(&self, endpoint: &str, key: &str) -> PathBuf {
+        self.directory
+            .join(endpoint)
+            .join(format!("{}.json", escape(key)))
+    }
+}
+
+// escape everything but [A-Za-z0-9._-] so that any key is a valid and unique file name
+fn escape(key: &str) -> String {
+    let mut escaped = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'-' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+fn read_dir(directory: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(directory).map_err(|source| io_error(directory, source))?;
+    entries
+        .map(|entry| {
+            entry
+                .map(|entry| entry.path())
+                .map_err(|source| io_error(directory, source))
+        })
+        .collect()
+}
+
+// the .json entry files of an endpoint directory
+fn entry_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    Ok(read_dir(directory)?
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn not_cached(endpoint: &str, key: &str) -> Error {
+    Error::NotCached {
+        endpoint: endpoint.to_string(),
+        key: key.to_string(),
+    }
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> Error {
+    Error::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // empty cache directory of a test
+    fn directory(name: &str) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("brickstats-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory.display().to_string()
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn absent_values_are_known_offline() {
+        let directory = directory("absent");
+        let ttl = Duration::from_secs(60);
+
+        let online = Cache::new(&directory, ttl, CacheMode::Normal);
+        let fetched = online
+            .get_many_or_fetch(
+                "parts",
+                &keys(&["3001", "zzz"]),
+                |missing| {
+                    assert_eq!(missing, keys(&["3001", "zzz"]));
+                    Ok(vec!["3001".to_string()])
+                },
+                |part: &String| part.clone(),
+            )
+            .unwrap();
+        assert_eq!(fetched, keys(&["3001"]));
+
+        let offline = Cache::new(&directory, ttl, CacheMode::Offline);
+        let cached = offline
+            .get_many_or_fetch(
+                "parts",
+                &keys(&["3001", "zzz"]),
+                |_| panic!("offline mode must not fetch"),
+                |part: &String| part.clone(),
+            )
+            .unwrap();
+        assert_eq!(cached, keys(&["3001"]));
+
+        // keys never requested are still not cached
+        let uncached = offline.get_many_or_fetch(
+            "parts",
+            &keys(&["3002"]),
+            |_| panic!("offline mode must not fetch"),
+            |part: &String| part.clone(),
+        );
+        assert!(matches!(uncached, Err(Error::NotCached { .. })));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use super::error::Result;
+use super::model::{Color, InventoryPart, Part, PartCategory};
+use super::Database;
+use std::collections::HashMap;
+
+/// Parts, colors and part categories indexed by their ids, for constant time lookups
+/// while building datasets of large inventories.
+#[derive(Debug, Clone, Default)]
+pub struct Catalogue {
+    parts: HashMap<String, Part>,
+    colors: HashMap<i32, Color>,
+    categories: HashMap<i32, PartCategory>,
+}
+
+impl Catalogue {
+    pub fn new(parts: Vec<Part>, colors: Vec<Color>, categories: Vec<PartCategory>) -> Catalogue {
+        Catalogue {
+            parts: parts
+                .into_iter()
+                .map(|part| (part.part_num.clone(), part))
+                .collect(),
+            colors: colors.into_iter().map(|color| (color.id, color)).collect(),
+            categories: categories
+                .into_iter()
+                .map(|category| (category.id, category))
+                .collect(),
+        }
+    }
+
+    /// Looks up all colors and categories and the details of the parts in the inventory.
+    pub fn load(database: &dyn Database, inventory_parts: &[InventoryPart]) -> Result<Catalogue> {
+        Ok(Catalogue::new(
+            database.parts(inventory_parts)?,
+            database.all_colors()?,
+            database.all_categories()?,
+        ))
+    }
+
+    pub fn part(&self, part_num: &str) -> Option<&Part> {
+        self.parts.get(part_num)
+    }
+
+    pub fn color(&self, color_id: i32) -> Option<&Color> {
+        self.colors.get(&color_id)
+    }
+
+    /// All colors, in no particular order.
+    pub fn colors(&self) -> impl Iterator<Item = &Color> {
+        self.colors.values()
+    }
+
+    pub fn category(&self, category_id: i32) -> Option<&PartCategory> {
+        self.categories.get(&category_id)
+    }
+}
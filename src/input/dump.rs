@@ -0,0 +1,601 @@
+//! Offline access to the rebrickable csv database dumps
+//! <https://rebrickable.com/downloads/>
+use super::error::{Error, Result};
+use super::model::{
+    deserialize_flag, Color, InventoryMinifig, InventoryPart, InventorySet, Part, PartCategory,
+    Set, Theme, UserList,
+};
+use super::Database;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// files of the dump, as named on the downloads page (unzipped)
+const COLORS_FILE: &str = "colors.csv";
+const CATEGORIES_FILE: &str = "part_categories.csv";
+const PARTS_FILE: &str = "parts.csv";
+const PART_RELATIONSHIPS_FILE: &str = "part_relationships.csv";
+const SETS_FILE: &str = "sets.csv";
+const THEMES_FILE: &str = "themes.csv";
+const INVENTORIES_FILE: &str = "inventories.csv";
+const INVENTORY_PARTS_FILE: &str = "inventory_parts.csv";
+const INVENTORY_MINIFIGS_FILE: &str = "inventory_minifigs.csv";
+const MINIFIGS_FILE: &str = "minifigs.csv";
+const INVENTORY_SETS_FILE: &str = "inventory_sets.csv";
+
+// row of inventories.csv, every version of the inventory of a set or minifig
+#[derive(Debug, Clone, Deserialize)]
+struct InventoryRow {
+    id: i32,
+    set_num: String,
+    version: i32,
+}
+
+// row of inventory_parts.csv, which is too large to keep in memory
+#[derive(Debug, Deserialize)]
+struct InventoryPartRow {
+    inventory_id: i32,
+    part_num: String,
+    color_id: i32,
+    quantity: i32,
+    #[serde(deserialize_with = "deserialize_flag")]
+    is_spare: bool,
+}
+
+// row of inventory_minifigs.csv
+#[derive(Debug, Deserialize)]
+struct InventoryMinifigRow {
+    inventory_id: i32,
+    fig_num: String,
+    quantity: i32,
+}
+
+// row of inventory_sets.csv
+#[derive(Debug, Deserialize)]
+struct InventorySetRow {
+    inventory_id: i32,
+    set_num: String,
+    quantity: i32,
+}
+
+// row of minifigs.csv
+#[derive(Debug, Deserialize)]
+struct MinifigRow {
+    fig_num: String,
+    name: String,
+}
+
+// row of part_relationships.csv
+#[derive(Debug, Deserialize)]
+struct PartRelationshipRow {
+    rel_type: String,
+    child_part_num: String,
+    parent_part_num: String,
+}
+
+/// Unzipped rebrickable database dumps in a local directory.
+pub struct RebrickableDump {
+    directory: PathBuf,
+    // tables are loaded on first use
+    colors: OnceCell<Vec<Color>>,
+    categories: OnceCell<Vec<PartCategory>>,
+    inventories: OnceCell<Vec<InventoryRow>>,
+}
+
+impl RebrickableDump {
+    pub fn new(directory: &str) -> Result<RebrickableDump> {
+        let directory = PathBuf::from(directory);
+        if !directory.is_dir() {
+            return Err(Error::Io {
+                path: directory.display().to_string(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "database dump directory does not exist",
+                ),
+            });
+        }
+        Ok(RebrickableDump {
+            directory,
+            colors: OnceCell::new(),
+            categories: OnceCell::new(),
+            inventories: OnceCell::new(),
+        })
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.directory.join(file)
+    }
+
+    fn inventory_rows(&self) -> Result<&[InventoryRow]> {
+        read_table_once(&self.inventories, &self.path(INVENTORIES_FILE))
+    }
+
+    // id of the first inventory version of every set and minifig
+    // the dump contains every inventory version of a set, the api answers with the first one
+    fn first_inventory_ids(&self) -> Result<HashMap<&str, i32>> {
+        let mut inventory_ids: HashMap<&str, (i32, i32)> = HashMap::new();
+        for inventory in self.inventory_rows()? {
+            let entry = inventory_ids
+                .entry(inventory.set_num.as_str())
+                .or_insert((inventory.version, inventory.id));
+            if inventory.version < entry.0 {
+                *entry = (inventory.version, inventory.id);
+            }
+        }
+        Ok(inventory_ids
+            .into_iter()
+            .map(|(set_num, (_, id))| (set_num, id))
+            .collect())
+    }
+
+    // year range of the sets each of the given parts appears in
+    fn part_years(&self, part_nums: &HashSet<&str>) -> Result<HashMap<String, (i32, i32)>> {
+        let sets: Vec<Set> = read_table(&self.path(SETS_FILE))?;
+        let set_years: HashMap<&str, i32> = sets
+            .iter()
+            .map(|set| (set.set_num.as_str(), set.year))
+            .collect();
+        // minifig inventories have no year and are skipped
+        let inventory_years: HashMap<i32, i32> = self
+            .inventory_rows()?
+            .iter()
+            .filter_map(|inventory| {
+                set_years
+                    .get(inventory.set_num.as_str())
+                    .map(|year| (inventory.id, *year))
+            })
+            .collect();
+
+        let mut years: HashMap<String, (i32, i32)> = HashMap::new();
+        for_each_row(&self.path(INVENTORY_PARTS_FILE), |row: InventoryPartRow| {
+            if !part_nums.contains(row.part_num.as_str()) {
+                return;
+            }
+            if let Some(year) = inventory_years.get(&row.inventory_id) {
+                let range = years.entry(row.part_num).or_insert((*year, *year));
+                range.0 = range.0.min(*year);
+                range.1 = range.1.max(*year);
+            }
+        })?;
+        Ok(years)
+    }
+
+    // inventories of the sets by set number, with or without the parts of their minifigs
+    fn set_inventories(
+        &self,
+        set_nums: &[String],
+        minifig_parts: bool,
+    ) -> Result<HashMap<String, Vec<InventoryPart>>> {
+        let inventory_ids = self.first_inventory_ids()?;
+        let inventory_id = |set_num: &str| inventory_ids.get(set_num).copied();
+
+        // map every inventory to the sets containing its parts and how many times
+        let mut multipliers: HashMap<i32, HashMap<&str, i32>> = HashMap::new();
+        let mut set_inventory_ids: HashMap<i32, &str> = HashMap::new();
+        for set_num in set_nums {
+            if let Some(id) = inventory_id(set_num) {
+                multipliers.entry(id).or_default().insert(set_num, 1);
+                set_inventory_ids.insert(id, set_num);
+            }
+        }
+        // like the api with inc_minifig_parts=1, minifigs are dissolved into their parts
+        if minifig_parts {
+            let inventory_minifigs: Vec<InventoryMinifigRow> =
+                read_table(&self.path(INVENTORY_MINIFIGS_FILE))?;
+            for inventory_minifig in &inventory_minifigs {
+                let set_num = match set_inventory_ids.get(&inventory_minifig.inventory_id) {
+                    Some(set_num) => *set_num,
+                    None => continue,
+                };
+                match inventory_id(&inventory_minifig.fig_num) {
+                    Some(minifig_inventory_id) => {
+                        *multipliers
+                            .entry(minifig_inventory_id)
+                            .or_default()
+                            .entry(set_num)
+                            .or_insert(0) += inventory_minifig.quantity;
+                    }
+                    None => println!(
+                        "Warning: no inventory for minifig {} in set {}",
+                        inventory_minifig.fig_num, set_num
+                    ),
+                }
+            }
+        }
+
+        // sets without inventory have no parts
+        let mut inventories: HashMap<String, Vec<InventoryPart>> = set_nums
+            .iter()
+            .map(|set_num| (set_num.clone(), Vec::new()))
+            .collect();
+        for_each_row(&self.path(INVENTORY_PARTS_FILE), |row: InventoryPartRow| {
+            if let Some(sets) = multipliers.get(&row.inventory_id) {
+                for (set_num, multiplier) in sets {
+                    inventories
+                        .get_mut(*set_num)
+                        .unwrap()
+                        .push(InventoryPart::new(
+                            row.part_num.clone(),
+                            row.color_id,
+                            row.quantity * multiplier,
+                            row.is_spare,
+                        ));
+                }
+            }
+        })?;
+        // same order as the api (ordering=color)
+        for inventory_parts in inventories.values_mut() {
+            inventory_parts.sort_by_key(|inventory_part| inventory_part.color_id);
+        }
+        Ok(inventories)
+    }
+}
+
+impl Database for RebrickableDump {
+    fn inventory(&self, set_num: &str) -> Result<Vec<InventoryPart>> {
+        let mut inventories = self.set_inventories(&[set_num.to_string()], true)?;
+        Ok(inventories.remove(set_num).unwrap_or_default())
+    }
+
+    fn inventory_without_minifigs(&self, set_num: &str) -> Result<Vec<InventoryPart>> {
+        let mut inventories = self.set_inventories(&[set_num.to_string()], false)?;
+        Ok(inventories.remove(set_num).unwrap_or_default())
+    }
+
+    fn minifigs(&self, set_num: &str) -> Result<Vec<InventoryMinifig>> {
+        let inventory_id = match self.first_inventory_ids()?.get(set_num) {
+            Some(inventory_id) => *inventory_id,
+            None => return Ok(Vec::new()),
+        };
+        let inventory_minifigs: Vec<InventoryMinifigRow> =
+            read_table(&self.path(INVENTORY_MINIFIGS_FILE))?;
+        let inventory_minifigs: Vec<InventoryMinifigRow> = inventory_minifigs
+            .into_iter()
+            .filter(|inventory_minifig| inventory_minifig.inventory_id == inventory_id)
+            .collect();
+        if inventory_minifigs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut names: HashMap<String, String> = HashMap::new();
+        for_each_row(&self.path(MINIFIGS_FILE), |row: MinifigRow| {
+            names.insert(row.fig_num, row.name);
+        })?;
+        Ok(inventory_minifigs
+            .into_iter()
+            .map(|inventory_minifig| InventoryMinifig {
+                name: names
+                    .get(&inventory_minifig.fig_num)
+                    .cloned()
+                    .unwrap_or_default(),
+                fig_num: inventory_minifig.fig_num,
+                quantity: inventory_minifig.quantity,
+            })
+            .collect())
+    }
+
+    fn minifig_inventory(&self, fig_num: &str) -> Result<Vec<InventoryPart>> {
+        // minifigs have inventories like sets
+        self.inventory(fig_num)
+    }
+
+    fn subsets(&self, set_num: &str) -> Result<Vec<InventorySet>> {
+        let inventory_id = match self.first_inventory_ids()?.get(set_num) {
+            Some(inventory_id) => *inventory_id,
+            None => return Ok(Vec::new()),
+        };
+        let inventory_sets: Vec<InventorySetRow> = read_table(&self.path(INVENTORY_SETS_FILE))?;
+        let inventory_sets: Vec<InventorySetRow> = inventory_sets
+            .into_iter()
+            .filter(|inventory_set| inventory_set.inventory_id == inventory_id)
+            .collect();
+        if inventory_sets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let names: HashMap<String, String> = self
+            .all_sets()?
+            .into_iter()
+            .map(|set| (set.set_num, set.name))
+            .collect();
+        Ok(inventory_sets
+            .into_iter()
+            .map(|inventory_set| InventorySet {
+                name: names
+                    .get(&inventory_set.set_num)
+                    .cloned()
+                    .unwrap_or_default(),
+                set_num: inventory_set.set_num,
+                quantity: inventory_set.quantity,
+            })
+            .collect())
+    }
+
+    fn all_colors(&self) -> Result<Vec<Color>> {
+        Ok(read_table_once(&self.colors, &self.path(COLORS_FILE))?.to_vec())
+    }
+
+    fn all_categories(&self) -> Result<Vec<PartCategory>> {
+        Ok(read_table_once(&self.categories, &self.path(CATEGORIES_FILE))?.to_vec())
+    }
+
+    fn parts(&self, inventory_parts: &[InventoryPart]) -> Result<Vec<Part>> {
+        let part_nums: HashSet<&str> = inventory_parts
+            .iter()
+            .map(|inventory_part| inventory_part.part_num.as_str())
+            .collect();
+
+        let parts: Vec<Part> = read_table(&self.path(PARTS_FILE))?;
+        let relationships: Vec<PartRelationshipRow> =
+            read_table(&self.path(PART_RELATIONSHIPS_FILE))?;
+        let years = self.part_years(&part_nums)?;
+        // relationships of every part, whether it is the child or the parent
+        let mut part_relationships: HashMap<&str, Vec<&PartRelationshipRow>> = HashMap::new();
+        for relationship in &relationships {
+            part_relationships
+                .entry(relationship.child_part_num.as_str())
+                .or_default()
+                .push(relationship);
+            if relationship.parent_part_num != relationship.child_part_num {
+                part_relationships
+                    .entry(relationship.parent_part_num.as_str())
+                    .or_default()
+                    .push(relationship);
+            }
+        }
+
+        // the dump has no per-part details, they are derived from the other tables
+        let parts: Vec<Part> = parts
+            .into_iter()
+            .filter(|part| part_nums.contains(part.part_num.as_str()))
+            .map(|mut part| {
+                (part.year_from, part.year_to) =
+                    years.get(&part.part_num).copied().unwrap_or((0, 0));
+                part.part_url = format!("https://rebrickable.com/parts/{}/", part.part_num);
+                let relationships = part_relationships.remove(part.part_num.as_str());
+                for relationship in relationships.into_iter().flatten() {
+                    add_relationship(&mut part, relationship);
+                }
+                part
+            })
+            .collect();
+        println!("Got part details for {} parts", parts.len());
+        Ok(parts)
+    }
+
+    fn all_sets(&self) -> Result<Vec<Set>> {
+        read_table(&self.path(SETS_FILE))
+    }
+
+    fn all_themes(&self) -> Result<Vec<Theme>> {
+        read_table(&self.path(THEMES_FILE))
+    }
+
+    fn inventories(&self, set_nums: &[String]) -> Result<HashMap<String, Vec<InventoryPart>>> {
+        self.set_inventories(set_nums, true)
+    }
+
+    fn user_set_lists(&self) -> Result<Vec<UserList>> {
+        Err(no_user_collections())
+    }
+
+    fn user_part_lists(&self) -> Result<Vec<UserList>> {
+        Err(no_user_collections())
+    }
+
+    fn user_sets(&self, _set_list_id: Option<i32>) -> Result<Vec<InventorySet>> {
+        Err(no_user_collections())
+    }
+
+    fn user_parts(&self, _part_list_id: Option<i32>) -> Result<Vec<InventoryPart>> {
+        Err(no_user_collections())
+    }
+
+    fn part_nums_by_external_id(
+        &self,
+        _catalog: &str,
+        ext_ids: &[String],
+    ) -> Result<HashMap<String, String>> {
+        // the dump has no external ids, known parts are assumed to have the same number in the
+        // other catalog, which holds for most LDraw parts
+        let ext_ids: HashSet<&str> = ext_ids.iter().map(|ext_id| ext_id.as_str()).collect();
+        let parts: Vec<Part> = read_table(&self.path(PARTS_FILE))?;
+        Ok(parts
+            .into_iter()
+            .filter(|part| ext_ids.contains(part.part_num.as_str()))
+            .map(|part| (part.part_num.clone(), part.part_num))
+            .collect())
+    }
+}
+
+fn no_user_collections() -> Error {
+    Error::Unsupported("the database dumps have no user collections, use the api".to_string())
+}
+
+// add the other part of a relationship to the prints, molds, alternates or print_of of part
+fn add_relationship(part: &mut Part, relationship: &PartRelationshipRow) {
+    let is_child = relationship.child_part_num == part.part_num;
+    if !is_child && relationship.parent_part_num != part.part_num {
+        return;
+    }
+    // the other part of the relationship, seen from this part
+    let other = if is_child {
+        relationship.parent_part_num.clone()
+    } else {
+        relationship.child_part_num.clone()
+    };
+    match relationship.rel_type.as_str() {
+        // child is a print of parent
+        "P" if is_child => part.print_of = Some(other),
+        "P" => part.prints.push(other),
+        "M" => part.molds.push(other),
+        "A" => part.alternates.push(other),
+        _ => {}
+    }
+}
+
+// read a whole table of the dump
+fn read_table<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let mut table = Vec::new();
+    for_each_row(path, |row| table.push(row))?;
+    Ok(table)
+}
+
+// read a table of the dump on first use only
+fn read_table_once<'a, T: DeserializeOwned>(
+    cell: &'a OnceCell<Vec<T>>,
+    path: &Path,
+) -> Result<&'a [T]> {
+    if let Some(table) = cell.get() {
+        return Ok(table);
+    }
+    let table = read_table(path)?;
+    Ok(cell.get_or_init(|| table))
+}
+
+// stream a table of the dump row by row
+fn for_each_row<T: DeserializeOwned>(path: &Path, mut f: impl FnMut(T)) -> Result<()> {
+    let csv_error = |source| Error::Csv {
+        path: path.display().to_string(),
+        source,
+    };
+    let mut rdr = csv::Reader::from_path(path).map_err(csv_error)?;
+    for result in rdr.deserialize() {
+        f(result.map_err(csv_error)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // a dump of the given csv files in the temp directory
+    fn dump(name: &str, files: &[(&str, &str)]) -> (PathBuf, RebrickableDump) {
+        let directory =
+            std::env::temp_dir().join(format!("brickstats-dump-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (file, text) in files {
+            fs::write(directory.join(file), text).unwrap();
+        }
+        let dump = RebrickableDump::new(&directory.display().to_string()).unwrap();
+        (directory, dump)
+    }
+
+    #[test]
+    fn inventories_are_the_first_version_with_minifig_parts() {
+        let (directory, dump) = dump(
+            "inventory",
+            &[
+                (
+                    INVENTORIES_FILE,
+                    "id,version,set_num\n1,2,1000-1\n2,1,1000-1\n3,1,fig-000001\n",
+                ),
+                (
+                    INVENTORY_PARTS_FILE,
+                    "inventory_id,part_num,color_id,quantity,is_spare,img_url\n\
+                     1,9999,0,1,f,\n\
+                     2,3001,4,2,f,\n\
+                     2,3001,4,1,t,\n\
+                     3,973,1,1,f,\n",
+                ),
+                (
+                    INVENTORY_MINIFIGS_FILE,
+                    "inventory_id,fig_num,quantity\n2,fig-000001,3\n",
+                ),
+            ],
+        );
+        let inventory = dump.inventory("1000-1").unwrap();
+        let inventory: Vec<(&str, i32, i32, bool)> = inventory
+            .iter()
+            .map(|part| {
+                (
+                    part.part_num.as_str(),
+                    part.color_id,
+                    part.quantity,
+                    part.is_spare,
+                )
+            })
+            .collect();
+        // part 9999 is only in the second version, the minifig counts three times
+        assert_eq!(
+            inventory,
+            vec![
+                ("973", 1, 3, false),
+                ("3001", 4, 2, false),
+                ("3001", 4, 1, true),
+            ]
+        );
+        let without_minifigs = dump.inventory_without_minifigs("1000-1").unwrap();
+        assert_eq!(without_minifigs.len(), 2);
+        assert!(without_minifigs.iter().all(|part| part.part_num == "3001"));
+        assert!(dump.inventory("2000-1").unwrap().is_empty());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn parts_have_years_and_relationships() {
+        let (directory, dump) = dump(
+            "parts",
+            &[
+                (
+                    PARTS_FILE,
+                    "part_num,name,part_cat_id,part_material\n\
+                     3001,Brick 2 x 4,11,Plastic\n\
+                     3001old,Brick 2 x 4 old,11,Plastic\n\
+                     3001pr01,Brick 2 x 4 printed,11,Plastic\n",
+                ),
+                (
+                    PART_RELATIONSHIPS_FILE,
+                    "rel_type,child_part_num,parent_part_num\n\
+                     P,3001pr01,3001\n\
+                     M,3001old,3001\n\
+                     A,3002,3003\n",
+                ),
+                (
+                    SETS_FILE,
+                    "set_num,name,year,theme_id,num_parts,img_url\n\
+                     1000-1,Bricks,1990,1,2,\n\
+                     2000-1,More Bricks,1995,1,1,\n",
+                ),
+                (
+                    INVENTORIES_FILE,
+                    "id,version,set_num\n1,1,1000-1\n2,1,2000-1\n",
+                ),
+                (
+                    INVENTORY_PARTS_FILE,
+                    "inventory_id,part_num,color_id,quantity,is_spare,img_url\n\
+                     1,3001,4,1,f,\n\
+                     2,3001,1,1,f,\n",
+                ),
+            ],
+        );
+        let inventory = [
+            InventoryPart::new("3001".to_string(), 4, 1, false),
+            InventoryPart::new("3001pr01".to_string(), 4, 1, false),
+        ];
+        let parts = dump.parts(&inventory).unwrap();
+        assert_eq!(parts.len(), 2);
+        let brick = parts.iter().find(|part| part.part_num == "3001").unwrap();
+        assert_eq!((brick.year_from, brick.year_to), (1990, 1995));
+        assert_eq!(brick.prints, vec!["3001pr01"]);
+        assert_eq!(brick.molds, vec!["3001old"]);
+        assert!(brick.alternates.is_empty());
+        let print = parts
+            .iter()
+            .find(|part| part.part_num == "3001pr01")
+            .unwrap();
+        assert_eq!(print.print_of.as_deref(), Some("3001"));
+        // parts in no set have no years
+        assert_eq!((print.year_from, print.year_to), (0, 0));
+
+        // without external ids only known part numbers are mapped, to themselves
+        let part_nums = dump
+            .part_nums_by_external_id("LDraw", &["3001".to_string(), "u9001".to_string()])
+            .unwrap();
+        assert_eq!(part_nums.len(), 1);
+        assert_eq!(part_nums["3001"], "3001");
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
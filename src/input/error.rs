@@ -0,0 +1,153 @@
+use std::fmt;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while fetching or reading rebrickable data.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or the response could not be read.
+    Network {
+        url: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Any non-200 status without a more specific variant.
+    HttpStatus {
+        url: String,
+        status: u16,
+        body: String,
+    },
+    /// The api token was rejected (401/403).
+    Unauthorized { url: String },
+    /// The requested object does not exist (404).
+    NotFound { url: String },
+    /// The api token was throttled (429), `retry_after` in seconds if sent by the server.
+    RateLimited {
+        url: String,
+        retry_after: Option<u64>,
+    },
+    /// The response did not match the expected schema at `path`, e.g. `results[3].part.part_num`.
+    Schema {
+        url: String,
+        path: String,
+        message: String,
+    },
+    /// The set number is not in `12345-1` format.
+    InvalidSetNumber(String),
+    /// A file could not be read or written.
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A csv file could not be parsed.
+    Csv { path: String, source: csv::Error },
+    /// Any other part list file could not be parsed.
+    InvalidFile { path: String, message: String },
+    /// A request still failed after retrying it this many times.
+    TooManyAttempts { attempts: u32, source: Box<Error> },
+    /// Replay mode was requested, but the request was never recorded.
+    NotRecorded { url: String },
+    /// Offline mode was requested, but the entry is not in the cache.
+    NotCached { endpoint: String, key: String },
+    /// Username or password were rejected while logging in.
+    LoginFailed { username: String },
+    /// The source of the data cannot answer the query, e.g. user collections from the dumps.
+    Unsupported(String),
+}
+
+impl Error {
+    /// Distinct process exit code per kind of error, for scripts.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Network { .. } => 2,
+            Error::HttpStatus { .. } => 3,
+            Error::Unauthorized { .. } | Error::LoginFailed { .. } => 4,
+            Error::NotFound { .. } => 5,
+            Error::RateLimited { .. } => 6,
+            Error::Schema { .. } => 7,
+            Error::InvalidSetNumber(_) => 8,
+            Error::Io { .. } => 9,
+            Error::Csv { .. } => 10,
+            Error::NotCached { .. } => 11,
+            Error::NotRecorded { .. } => 12,
+            Error::InvalidFile { .. } => 13,
+            Error::Unsupported(_) => 1,
+            // the reason for the last failed attempt is more useful to scripts
+            Error::TooManyAttempts { source, .. } => source.exit_code(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Network { url, source } => write!(f, "network error for {}: {}", url, source),
+            Error::HttpStatus { url, status, body } => {
+                write!(f, "{} answered with status {}: {}", url, status, body)
+            }
+            Error::Unauthorized { url } => write!(
+                f,
+                "{} rejected the api token, check secrets/api_token.txt",
+                url
+            ),
+            Error::NotFound { url } => write!(f, "{} does not exist", url),
+            Error::RateLimited { url, retry_after } => match retry_after {
+                Some(seconds) => write!(
+                    f,
+                    "rate limit exceeded for {}, retry after {} seconds",
+                    url, seconds
+                ),
+                None => write!(f, "rate limit exceeded for {}", url),
+            },
+            Error::Schema { url, path, message } => {
+                write!(
+                    f,
+                    "unexpected response from {} at {}: {}",
+                    url, path, message
+                )
+            }
+            Error::InvalidSetNumber(set_num) => write!(
+                f,
+                "invalid set number {}, expected a format like 12345-1",
+                set_num
+            ),
+            Error::Io { path, source } => write!(f, "error reading {}: {}", path, source),
+            Error::Csv { path, source } => write!(f, "error parsing {}: {}", path, source),
+            Error::InvalidFile { path, message } => {
+                write!(f, "error parsing {}: {}", path, message)
+            }
+            Error::TooManyAttempts { attempts, source } => {
+                write!(f, "giving up after {} attempts: {}", attempts, source)
+            }
+            Error::NotRecorded { url } => {
+                write!(
+                    f,
+                    "no recorded response for {} in the fixture directory",
+                    url
+                )
+            }
+            Error::NotCached { endpoint, key } => write!(
+                f,
+                "{} {} is not cached and fetching is disabled in offline mode",
+                endpoint, key
+            ),
+            Error::LoginFailed { username } => write!(
+                f,
+                "rebrickable rejected the username or password of {}",
+                username
+            ),
+            Error::Unsupported(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network { source, .. } => Some(source.as_ref()),
+            Error::Io { source, .. } => Some(source),
+            Error::Csv { source, .. } => Some(source),
+            Error::TooManyAttempts { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Record api responses to a fixture directory and replay them without network access.
+use super::error::{Error, Result};
+use super::http::{Response, Transport};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// one recorded request/response pair, stored as {directory}/{hash of url}.json
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    // request url without the api key
+    url: String,
+    status: u16,
+    retry_after: Option<u64>,
+    body: String,
+}
+
+/// Sends requests through another transport and records every response, without the api key.
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    directory: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn Transport>, directory: &str) -> Result<RecordingTransport> {
+        fs::create_dir_all(directory).map_err(|source| Error::Io {
+            path: directory.to_string(),
+            source,
+        })?;
+        Ok(RecordingTransport {
+            inner,
+            directory: PathBuf::from(directory),
+        })
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn get(&self, url: &str) -> Result<Response> {
+        let response = self.inner.get(url)?;
+        // the next links of paginated responses contain the api key and user token as well
+        let mut body = response.body.clone();
+        if let Some(key) = api_key(url) {
+            body = body.replace(key, "");
+        }
+        if let Some(user_token) = user_token(url) {
+            body = body.replace(&format!("/users/{}/", user_token), "/users/-/");
+        }
+        let url = without_secrets(url);
+        let path = fixture_path(&self.directory, &url);
+        // a retried request overwrites the failed attempt, so the final answer is replayed
+        let fixture = Fixture {
+            url,
+            status: response.status,
+            retry_after: response.retry_after,
+            body,
+        };
+        let text = serde_json::to_string_pretty(&fixture).expect("fixtures are serializable");
+        fs::write(&path, text).map_err(|source| Error::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Ok(response)
+    }
+
+    // logins are sent but never recorded, they contain the password
+    fn post(&self, url: &str, form: &[(&str, &str)]) -> Result<Response> {
+        self.inner.post(url, form)
+    }
+}
+
+/// Answers requests from a fixture directory, unrecorded requests are an error.
+pub struct ReplayTransport {
+    directory: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(directory: &str) -> Result<ReplayTransport> {
+        let directory = PathBuf::from(directory);
+        if !directory.is_dir() {
+            return Err(Error::Io {
+                path: directory.display().to_string(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "fixture directory does not exist",
+                ),
+            });
+        }
+        Ok(ReplayTransport { directory })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn get(&self, url: &str) -> Result<Response> {
+        let url = without_secrets(url);
+        let path = fixture_path(&self.directory, &url);
+        if !path.exists() {
+            return Err(Error::NotRecorded { url });
+        }
+        let text = fs::read_to_string(&path).map_err(|source| Error::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let fixture: Fixture = serde_json::from_str(&text).map_err(|e| Error::Schema {
+            url: path.display().to_string(),
+            path: "$".to_string(),
+            message: format!("invalid fixture: {}", e),
+        })?;
+        Ok(Response {
+            status: fixture.status,
+            retry_after: fixture.retry_after,
+            body: fixture.body,
+        })
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+fn api_key(url: &str) -> Option<&str> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|parameter| parameter.strip_prefix("key="))
+        .filter(|key| !key.is_empty())
+}
+
+// fixtures must not leak the api key or user token and must replay with any other ones
+fn without_secrets(url: &str) -> String {
+    let url = without_user_token(url);
+    match url.split_once('?') {
+        Some((path, query)) => {
+            let query: Vec<&str> = query
+                .split('&')
+                .filter(|parameter| !parameter.starts_with("key="))
+                .collect();
+            if query.is_empty() {
+                path.to_string()
+            } else {
+                format!("{}?{}", path, query.join("&"))
+            }
+        }
+        None => url.to_string(),
+    }
+}
+
+// user collections have the user token in their path, e.g. users/{token}/allparts/
+fn user_token(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("/users/")?;
+    let (user_token, _) = rest.split_once('/')?;
+    // the login endpoint users/_token/ has no user token
+    Some(user_token).filter(|user_token| !user_token.is_empty() && *user_token != "_token")
+}
+
+fn without_user_token(url: &str) -> String {
+    match user_token(url) {
+        Some(user_token) => url.replacen(&format!("/users/{}/", user_token), "/users/-/", 1),
+        None => url.to_string(),
+    }
+}
+
+// urls are too long for file names, so they are hashed with 64 bit fnv-1a,
+// which unlike the std hasher is stable across rust versions and machines
+fn fixture_path(directory: &std::path::Path, url: &str) -> PathBuf {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in url.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    directory.join(format!("{:016x}.json", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::FixtureTransport;
+
+    #[test]
+    fn recorded_fixtures_contain_no_secrets() {
+        let directory = std::env::temp_dir().join(format!(
+            "brickstats-fixtures-secrets-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        let url = "http://fixtures/api/v3/users/usertoken123/allparts/?page_size=500&key=apikey456";
+        let mut inner = FixtureTransport::new();
+        inner.insert(
+            url,
+            200,
+            r#"{"next": "http://fixtures/api/v3/users/usertoken123/allparts/?page=2&page_size=500&key=apikey456", "results": []}"#,
+        );
+        let recording =
+            RecordingTransport::new(Box::new(inner), &directory.display().to_string()).unwrap();
+
+        // the caller still gets the original response
+        let response = recording.get(url).unwrap();
+        assert!(response.body.contains("usertoken123"));
+
+        let path = fixture_path(
+            &directory,
+            "http://fixtures/api/v3/users/-/allparts/?page_size=500",
+        );
+        let text = fs::read_to_string(path).unwrap();
+        assert!(!text.contains("usertoken123"));
+        assert!(!text.contains("apikey456"));
+        assert!(text.contains("/users/-/allparts/?page=2"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn login_url_keeps_its_path() {
+        assert_eq!(
+            without_secrets("http://fixtures/api/v3/users/_token/?key=apikey456"),
+            "http://fixtures/api/v3/users/_token/"
+        );
+    }
+}
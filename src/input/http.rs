@@ -0,0 +1,442 @@
+//! Http client shared by all requests to the rebrickable api,
+//! limits the request rate and retries throttled or failed requests.
+use super::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Base url of the rebrickable api v3.
+pub const DEFAULT_BASE_URL: &str = "https://rebrickable.com/api/v3/";
+// rebrickable allows about one request per second on average, with short bursts
+pub const REQUESTS_PER_SECOND: f64 = 1.0;
+pub const BURST: u32 = 3;
+pub const MAX_ATTEMPTS: u32 = 5;
+// first backoff delay, doubled on every further attempt
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+// token bucket: every request takes a token, tokens refill at a fixed rate up to the burst size
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(refill_per_second: f64, capacity: u32) -> TokenBucket {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refill_per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // take a token, returns how long to wait before the request may be sent
+    fn take(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_per_second)
+        }
+    }
+}
+
+/// Response to a request, reduced to what the client needs.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    /// Value of the Retry-After header in seconds, if any.
+    pub retry_after: Option<u64>,
+    pub body: String,
+}
+
+/// Sends the actual requests, replaceable to run against fixtures instead of the network.
+pub trait Transport {
+    /// Sends a get request. Only failures to get any response are errors.
+    fn get(&self, url: &str) -> Result<Response>;
+
+    /// Sends a post request with a form body, e.g. to log in. Transports that only answer
+    /// recorded get requests answer 405.
+    fn post(&self, url: &str, _form: &[(&str, &str)]) -> Result<Response> {
+        Ok(Response {
+            status: 405,
+            retry_after: None,
+            body: format!("post requests to {} are not supported", url),
+        })
+    }
+
+    /// Local transports answer without reaching rebrickable, so they are not rate limited.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// Sends requests over the network.
+#[derive(Default)]
+pub struct ReqwestTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> ReqwestTransport {
+        ReqwestTransport {
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn get(&self, url: &str) -> Result<Response> {
+        let network_error = |source: reqwest::Error| Error::Network {
+            url: url.to_string(),
+            source: Box::new(source),
+        };
+        let response = self.client.get(url).send().map_err(network_error)?;
+        response_of(response).map_err(network_error)
+    }
+
+    fn post(&self, url: &str, form: &[(&str, &str)]) -> Result<Response> {
+        let network_error = |source: reqwest::Error| Error::Network {
+            url: url.to_string(),
+            source: Box::new(source),
+        };
+        let response = self
+            .client
+            .post(url)
+            .form(form)
+            .send()
+            .map_err(network_error)?;
+        response_of(response).map_err(network_error)
+    }
+}
+
+// status, Retry-After and body of a response
+fn response_of(response: reqwest::blocking::Response) -> reqwest::Result<Response> {
+    let status = response.status().as_u16();
+    // only the delay-seconds form of Retry-After is used by rebrickable
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let body = response.text()?;
+    Ok(Response {
+        status,
+        retry_after,
+        body,
+    })
+}
+
+/// Answers requests from responses held in memory, unknown urls are answered with 404.
+#[derive(Default)]
+pub struct FixtureTransport {
+    responses: HashMap<String, Response>,
+}
+
+impl FixtureTransport {
+    pub fn new() -> FixtureTransport {
+        FixtureTransport::default()
+    }
+
+    /// Answers get requests for `url` with status and body.
+    pub fn insert(&mut self, url: &str, status: u16, body: &str) {
+        self.responses.insert(
+            url.to_string(),
+            Response {
+                status,
+                retry_after: None,
+                body: body.to_string(),
+            },
+        );
+    }
+}
+
+impl Transport for FixtureTransport {
+    fn get(&self, url: &str) -> Result<Response> {
+        Ok(self.responses.get(url).cloned().unwrap_or(Response {
+            status: 404,
+            retry_after: None,
+            body: format!("no fixture for {}", url),
+        }))
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+pub struct HttpClient {
+    transport: Box<dyn Transport>,
+    base_url: String,
+    limiter: Mutex<TokenBucket>,
+    max_attempts: u32,
+}
+
+impl HttpClient {
+    pub fn new(requests_per_second: f64, burst: u32, max_attempts: u32) -> HttpClient {
+        HttpClient {
+            transport: Box::new(ReqwestTransport::new()),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            limiter: Mutex::new(TokenBucket::new(requests_per_second, burst)),
+            max_attempts,
+        }
+    }
+
+    pub fn set_transport(&mut self, transport: Box<dyn Transport>) {
+        self.transport = transport;
+    }
+
+    // base url of the api, e.g. a local stand-in server or a mirror
+    pub fn set_base_url(&mut self, base_url: &str) {
+        self.base_url = base_url.to_string();
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+    }
+
+    // absolute url of an endpoint path like "lego/colors/"
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // download url and parse the response body as json into T
+    // rate limited (429) and server errors (5xx) are retried with exponential backoff
+    pub fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.try_get_json(url) {
+                Ok(json) => return Ok(json),
+                Err(error) => error,
+            };
+            let delay = match retry_delay(&error, attempt) {
+                Some(delay) => delay,
+                None => return Err(error),
+            };
+            if attempt >= self.max_attempts {
+                return Err(Error::TooManyAttempts {
+                    attempts: attempt,
+                    source: Box::new(error),
+                });
+            }
+            println!(
+                "Warning: {}, retrying in {} seconds (attempt {} of {})",
+                error,
+                delay.as_secs_f32(),
+                attempt + 1,
+                self.max_attempts
+            );
+            sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    // send a form and parse the response body as json into T, without retrying
+    pub fn post_json<T: DeserializeOwned>(&self, url: &str, form: &[(&str, &str)]) -> Result<T> {
+        self.wait_for_rate_limit();
+        json_of(url, self.transport.post(url, form)?)
+    }
+
+    fn try_get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.wait_for_rate_limit();
+        println!("Downloading {}", url);
+        json_of(url, self.transport.get(url)?)
+    }
+
+    fn wait_for_rate_limit(&self) {
+        if !self.transport.is_local() {
+            let wait = self.limiter.lock().unwrap().take();
+            sleep(wait);
+        }
+    }
+}
+
+// the body of a successful response as json, other statuses as errors
+fn json_of<T: DeserializeOwned>(url: &str, response: Response) -> Result<T> {
+    let Response {
+        status,
+        retry_after,
+        body,
+    } = response;
+    let url = url.to_string();
+    match status {
+        200 => parse_json(&body).map_err(|(path, message)| Error::Schema { url, path, message }),
+        401 | 403 => Err(Error::Unauthorized { url }),
+        404 => Err(Error::NotFound { url }),
+        429 => Err(Error::RateLimited { url, retry_after }),
+        _ => Err(Error::HttpStatus { url, status, body }),
+    }
+}
+
+// parse json, on error returning where in the document it failed, e.g. "results[3].part.part_num"
+fn parse_json<T: DeserializeOwned>(body: &str) -> std::result::Result<T, (String, String)> {
+    let deserializer = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = match e.path().to_string() {
+            root if root == "." => "$".to_string(),
+            path => path,
+        };
+        let message = if e.inner().is_syntax() || e.inner().is_eof() {
+            format!("invalid json: {}", e.inner())
+        } else {
+            e.inner().to_string()
+        };
+        (path, message)
+    })
+}
+
+// how long to wait before retrying a failed attempt, None if the error is final
+// a server asking for a longer delay than the longest backoff is not waited for any longer
+fn retry_delay(error: &Error, attempt: u32) -> Option<Duration> {
+    match error {
+        Error::RateLimited {
+            retry_after: Some(seconds),
+            ..
+        } => Some(Duration::from_secs(*seconds).min(BACKOFF_MAX)),
+        Error::RateLimited { .. } | Error::Network { .. } => Some(backoff(attempt)),
+        Error::HttpStatus { status, .. } if *status >= 500 => Some(backoff(attempt)),
+        _ => None,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(BACKOFF_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    const URL: &str = "http://fixtures/api/v3/lego/colors/";
+
+    // answers every request with the next of its responses, then with 404
+    struct SequenceTransport {
+        responses: RefCell<VecDeque<Response>>,
+    }
+
+    impl SequenceTransport {
+        fn new(responses: Vec<(u16, Option<u64>, &str)>) -> SequenceTransport {
+            let responses = responses
+                .into_iter()
+                .map(|(status, retry_after, body)| Response {
+                    status,
+                    retry_after,
+                    body: body.to_string(),
+                })
+                .collect();
+            SequenceTransport {
+                responses: RefCell::new(responses),
+            }
+        }
+    }
+
+    impl Transport for SequenceTransport {
+        fn get(&self, url: &str) -> Result<Response> {
+            Ok(self.responses.borrow_mut().pop_front().unwrap_or(Response {
+                status: 404,
+                retry_after: None,
+                body: format!("no response left for {}", url),
+            }))
+        }
+    }
+
+    fn client(transport: impl Transport + 'static, max_attempts: u32) -> HttpClient {
+        let mut client = HttpClient::new(REQUESTS_PER_SECOND, BURST, max_attempts);
+        client.set_transport(Box::new(transport));
+        client
+    }
+
+    #[test]
+    fn bursts_are_sent_without_waiting() {
+        let mut bucket = TokenBucket::new(1.0, 3);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(), Duration::ZERO);
+        }
+        // the fourth request waits for about one refill
+        let wait = bucket.take();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(10), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn rate_limited_requests_are_retried_after_the_given_delay() {
+        let transport = SequenceTransport::new(vec![
+            (429, Some(0), "slow down"),
+            (429, Some(0), "slow down"),
+            (200, None, "[1, 2]"),
+        ]);
+        let values: Vec<i32> = client(transport, MAX_ATTEMPTS).get_json(URL).unwrap();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn retries_stop_after_the_last_attempt() {
+        let transport = SequenceTransport::new(vec![(429, Some(0), "slow down"); 3]);
+        let result: Result<Vec<i32>> = client(transport, 3).get_json(URL);
+        match result {
+            Err(Error::TooManyAttempts { attempts, source }) => {
+                assert_eq!(attempts, 3);
+                assert!(matches!(*source, Error::RateLimited { .. }));
+            }
+            other => panic!("expected too many attempts, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_errors_count_as_failed_attempts() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(URL, 503, "unavailable");
+        // a single attempt, so that the test does not wait for the backoff
+        let result: Result<Vec<i32>> = client(transport, 1).get_json(URL);
+        assert!(matches!(
+            result,
+            Err(Error::TooManyAttempts { attempts: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let transport = SequenceTransport::new(vec![(404, None, "not found"), (200, None, "[1]")]);
+        let result: Result<Vec<i32>> = client(transport, MAX_ATTEMPTS).get_json(URL);
+        assert!(matches!(result, Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_the_longest_backoff() {
+        let rate_limited = |retry_after| Error::RateLimited {
+            url: URL.to_string(),
+            retry_after,
+        };
+        assert_eq!(
+            retry_delay(&rate_limited(Some(5)), 1),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            retry_delay(&rate_limited(Some(86400)), 1),
+            Some(BACKOFF_MAX)
+        );
+        assert_eq!(retry_delay(&rate_limited(None), 2), Some(backoff(2)));
+        let not_found = Error::NotFound {
+            url: URL.to_string(),
+        };
+        assert_eq!(retry_delay(&not_found, 1), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! LDraw model files (.ldr) and multi-part documents (.mpd) with submodels
+//! <https://www.ldraw.org/article/218.html>
+use super::error::{Error, Result};
+use super::{merge_inventories, Database, InventoryPart};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// name of ldraw in the external ids of parts and colors
+const CATALOG: &str = "LDraw";
+// color of the referencing line, and its edge color which we treat the same
+const CURRENT_COLOR: i32 = 16;
+const EDGE_COLOR: i32 = 24;
+// deeper nesting can only be a reference cycle
+const MAX_DEPTH: usize = 64;
+
+// type 1 line, a part or submodel placed in a color
+struct Reference {
+    color: Option<i32>,
+    // normalized name, the key of submodels
+    file: String,
+    // name as written, to open submodels on case sensitive file systems
+    name: String,
+}
+
+// a model or submodel, by lowercase file name
+type Models = HashMap<String, Vec<Reference>>;
+
+/// Reads an LDraw model, flattening all submodels, and maps the LDraw part and color ids to
+/// rebrickable ones. Submodels are looked up in the document, then next to the file.
+pub fn inventory_from_ldraw_file(
+    path: &str,
+    database: &dyn Database,
+) -> Result<Vec<InventoryPart>> {
+    let (models, main) = read_model_files(path)?;
+    let mut parts: Vec<(String, i32, i32)> = Vec::new();
+    flatten(path, &models, &main, None, 0, &mut parts)?;
+
+    // map the ldraw ids to rebrickable ones
+    let mut ext_ids: Vec<String> = parts.iter().map(|(part, _, _)| part.clone()).collect();
+    ext_ids.sort();
+    ext_ids.dedup();
+    let part_nums = database.part_nums_by_external_id(CATALOG, &ext_ids)?;
+    for ext_id in ext_ids
+        .iter()
+        .filter(|ext_id| !part_nums.contains_key(*ext_id))
+    {
+        println!(
+            "Warning: no rebrickable part for ldraw part {}, keeping its number",
+            ext_id
+        );
+    }
+    let colors = database.all_colors()?;
+    let mut color_ids: HashMap<i32, i32> = HashMap::new();
+    for color in &colors {
+        let ext_ids = color.external_ids.get(CATALOG);
+        for ext_id in ext_ids
+            .into_iter()
+            .flat_map(|ext_ids| ext_ids.ext_ids.iter().flatten())
+        {
+            color_ids.entry(*ext_id).or_insert(color.id);
+        }
+    }
+    // rebrickable colors were derived from ldraw and mostly share their ids,
+    // which is all we know without external ids, e.g. in the database dumps
+    for color in &colors {
+        color_ids.entry(color.id).or_insert(color.id);
+    }
+
+    let mut inventory_parts = Vec::new();
+    for (part, color, quantity) in parts {
+        let color_id = match color_ids.get(&color) {
+            Some(color_id) => *color_id,
+            None => {
+                println!(
+                    "Warning: skipping part {} in unknown ldraw color {}",
+                    part, color
+                );
+                continue;
+            }
+        };
+        let part_num = part_nums.get(&part).cloned().unwrap_or(part);
+        inventory_parts.push(InventoryPart::new(part_num, color_id, quantity, false));
+    }
+    Ok(merge_inventories([(1, inventory_parts)]))
+}
+
+// read the models of a file and of all files of submodels it references,
+// returns them with the name of its main model
+fn read_model_files(path: &str) -> Result<(Models, String)> {
+    let mut models = Models::new();
+    let main = read_models(Path::new(path), &mut models)?;
+    // load submodels from other files until all references are resolved
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    loop {
+        let mut unresolved: Vec<(String, String)> = models
+            .values()
+            .flatten()
+            .filter(|reference| !is_part(&reference.file) && !models.contains_key(&reference.file))
+            .map(|reference| (reference.file.clone(), reference.name.clone()))
+            .collect();
+        if unresolved.is_empty() {
+            break;
+        }
+        unresolved.sort();
+        unresolved.dedup_by(|(file, _), (other, _)| file == other);
+        for (file, name) in unresolved {
+            match read_models(&find_file(directory, &name), &mut models) {
+                // the reference may include a directory, which the model name does not
+                Ok(main) => {
+                    models.entry(file).or_insert_with(|| {
+                        vec![Reference {
+                            color: Some(CURRENT_COLOR),
+                            file: main.clone(),
+                            name: main,
+                        }]
+                    });
+                }
+                // a missing submodel has no parts, but must not be looked up again
+                Err(_) => {
+                    println!("Warning: submodel {} of {} not found", name, path);
+                    models.insert(file, Vec::new());
+                }
+            }
+        }
+    }
+    Ok((models, main))
+}
+
+// the file of a submodel, names are case insensitive in ldraw but not on every file system
+fn find_file(directory: &Path, name: &str) -> PathBuf {
+    let path = directory.join(name);
+    if path.exists() {
+        return path;
+    }
+    let (subdirectory, file_name) = match name.rsplit_once('/') {
+        Some((subdirectory, file_name)) => (directory.join(subdirectory), file_name),
+        None => (directory.to_path_buf(), name),
+    };
+    fs::read_dir(&subdirectory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|other| other.to_str())
+                .is_some_and(|other| other.eq_ignore_ascii_case(file_name))
+        })
+        .unwrap_or(path)
+}
+
+// read all models of a file into models, returns the name of its main model
+fn read_models(path: &Path, models: &mut Models) -> Result<String> {
+    let text = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let file_name = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .map(normalize)
+        .unwrap_or_default();
+
+    // a plain .ldr file is a single model, in an .mpd every 0 FILE starts a model
+    // and the first one is the main model
+    let mut main = None;
+    let mut current = Some(file_name.clone());
+    let mut references = Vec::new();
+    for line in text.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["0", "FILE", name @ ..] => {
+                if let Some(model) = current.take() {
+                    if model != file_name || !references.is_empty() {
+                        models.insert(model, std::mem::take(&mut references));
+                    }
+                }
+                let name = normalize(&name.join(" "));
+                main.get_or_insert_with(|| name.clone());
+                current = Some(name);
+            }
+            ["0", "NOFILE"] => {
+                if let Some(model) = current.take() {
+                    models.insert(model, std::mem::take(&mut references));
+                }
+            }
+            ["1", color, _, _, _, _, _, _, _, _, _, _, _, _, file @ ..] if !file.is_empty() => {
+                let name = file.join(" ").trim().replace('\\', "/");
+                references.push(Reference {
+                    color: color.parse().ok(),
+                    file: normalize(&name),
+                    name,
+                });
+            }
+            _ => {}
+        }
+    }
+    if let Some(model) = current {
+        models.insert(model, references);
+    }
+
+    // an .mpd is known by its file name too, so that other files can reference it
+    let main = main.unwrap_or_else(|| file_name.clone());
+    if main != file_name && !models.contains_key(&file_name) {
+        models.insert(
+            file_name,
+            vec![Reference {
+                color: Some(CURRENT_COLOR),
+                file: main.clone(),
+                name: main.clone(),
+            }],
+        );
+    }
+    Ok(main)
+}
+
+// collect (part, color, quantity) of a model and its submodels, with the color of the reference
+fn flatten(
+    path: &str,
+    models: &Models,
+    model: &str,
+    color: Option<i32>,
+    depth: usize,
+    parts: &mut Vec<(String, i32, i32)>,
+) -> Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(Error::InvalidFile {
+            path: path.to_string(),
+            message: format!("submodel {} references itself", model),
+        });
+    }
+    for reference in models.get(model).into_iter().flatten() {
+        // 16 and 24 inherit the color of the referencing line, parts at the top have none
+        let reference_color = match reference.color {
+            Some(CURRENT_COLOR | EDGE_COLOR) => color,
+            Some(color) => Some(color),
+            None => None,
+        };
+        if !is_part(&reference.file) {
+            flatten(
+                path,
+                models,
+                &reference.file,
+                reference_color,
+                depth + 1,
+                parts,
+            )?;
+            continue;
+        }
+        let part = part_id(&reference.file);
+        match reference_color {
+            Some(color) => parts.push((part, color, 1)),
+            // e.g. direct colors like 0x2FF0000, which have no rebrickable color
+            None => println!("Warning: skipping part {} without ldraw color", part),
+        }
+    }
+    Ok(())
+}
+
+// references to .dat files are parts, everything else is a model
+fn is_part(file: &str) -> bool {
+    file.ends_with(".dat")
+}
+
+// ldraw id of a part file, which may be referenced with its directory in the library,
+// e.g. parts\3005.dat or s\3001s01.dat
+fn part_id(file: &str) -> String {
+    let mut part = file.trim_end_matches(".dat");
+    while let Some(rest) = ["parts/", "p/", "s/"]
+        .iter()
+        .find_map(|directory| part.strip_prefix(directory))
+    {
+        part = rest;
+    }
+    part.to_string()
+}
+
+// ldraw file names are case insensitive and may use windows separators
+fn normalize(file: &str) -> String {
+    file.trim().to_lowercase().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // flattened (part, color, quantity) of an ldraw document in the temp directory
+    fn flattened(name: &str, text: &str) -> Result<Vec<(String, i32, i32)>> {
+        let path = std::env::temp_dir().join(format!(
+            "brickstats-ldraw-{}-{}.mpd",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        let mut models = Models::new();
+        let main = read_models(&path, &mut models);
+        fs::remove_file(&path).unwrap();
+        let mut parts = Vec::new();
+        flatten(
+            &path.display().to_string(),
+            &models,
+            &main?,
+            None,
+            0,
+            &mut parts,
+        )?;
+        Ok(parts)
+    }
+
+    #[test]
+    fn submodels_inherit_the_color_of_their_reference() {
+        let text = "0 FILE Main.ldr\n\
+            1 4 0 0 0 1 0 0 0 1 0 0 0 1 Wall.ldr\n\
+            1 1 0 0 0 1 0 0 0 1 0 0 0 1 wall.ldr\n\
+            1 14 0 0 0 1 0 0 0 1 0 0 0 1 3003.dat\n\
+            1 16 0 0 0 1 0 0 0 1 0 0 0 1 3004.dat\n\
+            0 NOFILE\n\
+            0 FILE wall.ldr\n\
+            1 16 0 0 0 1 0 0 0 1 0 0 0 1 3001.DAT\n\
+            1 24 0 0 0 1 0 0 0 1 0 0 0 1 3002.dat\n\
+            1 0 0 0 0 1 0 0 0 1 0 0 0 1 parts\\3005.dat\n";
+        let parts = flattened("colors", text).unwrap();
+        let parts: Vec<(&str, i32)> = parts
+            .iter()
+            .map(|(part, color, _)| (part.as_str(), *color))
+            .collect();
+        // the main model is the first one, 3004 in the current color has none at the top
+        assert_eq!(
+            parts,
+            vec![
+                ("3001", 4),
+                ("3002", 4),
+                ("3005", 0),
+                ("3001", 1),
+                ("3002", 1),
+                ("3005", 0),
+                ("3003", 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_cycles_are_invalid() {
+        let text = "0 FILE a.ldr\n\
+            1 4 0 0 0 1 0 0 0 1 0 0 0 1 b.ldr\n\
+            0 FILE b.ldr\n\
+            1 16 0 0 0 1 0 0 0 1 0 0 0 1 a.ldr\n";
+        let result = flattened("cycle", text);
+        assert!(matches!(result, Err(Error::InvalidFile { .. })));
+    }
+
+    #[test]
+    fn submodel_files_are_found_by_their_name() {
+        let directory =
+            std::env::temp_dir().join(format!("brickstats-ldraw-files-{}", std::process::id()));
+        fs::create_dir_all(directory.join("Sub")).unwrap();
+        let main = directory.join("Main.ldr");
+        fs::write(
+            &main,
+            "1 4 0 0 0 1 0 0 0 1 0 0 0 1 Sub\\SubModel.ldr\n\
+             1 1 0 0 0 1 0 0 0 1 0 0 0 1 other.LDR\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("Sub").join("SubModel.ldr"),
+            "1 16 0 0 0 1 0 0 0 1 0 0 0 1 3001.dat\n",
+        )
+        .unwrap();
+        // referenced in another case than its file name
+        fs::write(
+            directory.join("Other.ldr"),
+            "1 16 0 0 0 1 0 0 0 1 0 0 0 1 3002.dat\n",
+        )
+        .unwrap();
+
+        let path = main.display().to_string();
+        let (models, main) = read_model_files(&path).unwrap();
+        let mut parts = Vec::new();
+        flatten(&path, &models, &main, None, 0, &mut parts).unwrap();
+        assert_eq!(
+            parts,
+            vec![("3001".to_string(), 4, 1), ("3002".to_string(), 1, 1)]
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
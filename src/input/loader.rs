@@ -0,0 +1,685 @@
+use super::error::{Error, Result};
+use super::{
+    inventory_from_bricklink_file, inventory_from_file, inventory_from_ldraw_file,
+    inventory_from_lxf_file, Database, InventoryMinifig, InventoryPart, InventorySet, UserList,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// deeper nesting of sets in sets can only be a cycle
+const MAX_SET_DEPTH: usize = 8;
+
+/// Something an inventory can be loaded from, e.g. an official set or a part list file.
+pub trait InventoryLoader {
+    /// Short name for output file names, e.g. `10698-1`.
+    fn name(&self) -> String;
+
+    /// Name for chart titles, e.g. `Set 10698-1`.
+    fn title(&self) -> String;
+
+    /// Loads the inventory, using `database` to look up sets.
+    fn load(&self, database: &dyn Database, options: &LoadOptions) -> Result<Vec<InventoryPart>>;
+
+    /// Loads the minifigs as whole units, whose parts are also part of the inventory.
+    /// Only sets know their minifigs.
+    fn minifigs(&self, _database: &dyn Database) -> Result<Vec<InventoryMinifig>> {
+        Ok(Vec::new())
+    }
+
+    /// Loads the sets contained in the inventory, e.g. the sets of a bundle, whose parts
+    /// are also part of the inventory. Only sets contain other sets.
+    fn subsets(&self, _database: &dyn Database) -> Result<Vec<InventorySet>> {
+        Ok(Vec::new())
+    }
+}
+
+/// How the minifigs of sets are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MinifigMode {
+    /// As their parts, which are part of the inventory.
+    #[default]
+    Parts,
+    /// As whole units, see [`InventoryLoader::minifigs`], their parts are left out.
+    Whole,
+    /// As whole units and as their parts.
+    Both,
+}
+
+/// What loaded inventories include.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Keep the spare parts of sets and spare rows of part lists, flagged as spares.
+    pub spares: bool,
+    /// Leave out the parts of minifigs of sets when they are counted as whole units.
+    pub minifigs: MinifigMode,
+}
+
+impl LoadOptions {
+    // leave out the parts the options exclude
+    fn filter(&self, inventory: Vec<InventoryPart>) -> Vec<InventoryPart> {
+        if self.spares {
+            return inventory;
+        }
+        inventory
+            .into_iter()
+            .filter(|inventory_part| !inventory_part.is_spare)
+            .collect()
+    }
+}
+
+// sets without inventory or with a set number the api does not accept, e.g. promotional
+// sets in a bundle, are skipped with a warning instead of failing the whole inventory
+fn skip_unknown_set<T>(set_num: &str, result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::NotFound { .. }) | Err(Error::InvalidSetNumber(_)) => {
+            println!("Warning: skipping set {} without inventory", set_num);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Inventory of an official set.
+pub struct SetInventory {
+    set_num: String,
+}
+
+impl SetInventory {
+    /// Accepts set numbers in `12345-1` or `12345` format, the latter meaning version 1.
+    pub fn new(set: &str) -> SetInventory {
+        // unify set_num_raw to format "12345-1"
+        let set_num = if set.contains('-') {
+            set.to_owned()
+        } else {
+            format!("{}-1", set)
+        };
+        SetInventory { set_num }
+    }
+
+    pub fn set_num(&self) -> &str {
+        &self.set_num
+    }
+
+    // parts of the set and of all sets it contains, each counted by its quantity
+    // bundles have no parts of their own, unknown sets count as empty
+    fn parts(
+        &self,
+        database: &dyn Database,
+        options: &LoadOptions,
+        depth: usize,
+    ) -> Result<Vec<InventoryPart>> {
+        let inventory = match options.minifigs {
+            MinifigMode::Whole => database.inventory_without_minifigs(&self.set_num),
+            MinifigMode::Parts | MinifigMode::Both => database.inventory(&self.set_num),
+        };
+        let mut inventories = match inventory {
+            Ok(inventory) => vec![(1, inventory)],
+            Err(Error::NotFound { .. }) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for subset in self.nested_subsets(database, depth)? {
+            let subset_inventory = SetInventory::new(&subset.set_num);
+            let parts = subset_inventory.parts(database, options, depth + 1);
+            if let Some(parts) = skip_unknown_set(&subset.set_num, parts)? {
+                inventories.push((subset.quantity, parts));
+            }
+        }
+        Ok(merge_inventories(inventories))
+    }
+
+    // minifigs of the set and of all sets it contains, each counted by its quantity
+    fn all_minifigs(&self, database: &dyn Database, depth: usize) -> Result<Vec<InventoryMinifig>> {
+        let mut minifigs = vec![(1, database.minifigs(&self.set_num)?)];
+        for subset in self.nested_subsets(database, depth)? {
+            let subset_inventory = SetInventory::new(&subset.set_num);
+            let subset_minifigs = subset_inventory.all_minifigs(database, depth + 1);
+            if let Some(subset_minifigs) = skip_unknown_set(&subset.set_num, subset_minifigs)? {
+                minifigs.push((subset.quantity, subset_minifigs));
+            }
+        }
+        Ok(merge_minifigs(minifigs))
+    }
+
+    // the sets contained in this set, unless it is nested too deep
+    fn nested_subsets(&self, database: &dyn Database, depth: usize) -> Result<Vec<InventorySet>> {
+        if depth >= MAX_SET_DEPTH {
+            println!(
+                "Warning: ignoring the sets contained in set {}, nested too deep",
+                self.set_num
+            );
+            return Ok(Vec::new());
+        }
+        match database.subsets(&self.set_num) {
+            Err(Error::NotFound { .. }) => Ok(Vec::new()),
+            subsets => subsets,
+        }
+    }
+}
+
+impl InventoryLoader for SetInventory {
+    fn name(&self) -> String {
+        self.set_num.clone()
+    }
+
+    fn title(&self) -> String {
+        format!("Set {}", self.set_num)
+    }
+
+    fn load(&self, database: &dyn Database, options: &LoadOptions) -> Result<Vec<InventoryPart>> {
+        let not_found = || Error::NotFound {
+            url: format!("inventory for set {}", self.set_num),
+        };
+        // sets with only spares have an inventory, even if nothing of it is kept,
+        // and so have sets with only minifigs counted as whole units, e.g. minifig packs
+        let inventory = self.parts(database, options, 0)?;
+        if inventory.is_empty()
+            && (options.minifigs != MinifigMode::Whole || self.minifigs(database)?.is_empty())
+        {
+            return Err(not_found());
+        }
+        Ok(options.filter(inventory))
+    }
+
+    fn minifigs(&self, database: &dyn Database) -> Result<Vec<InventoryMinifig>> {
+        self.all_minifigs(database, 0)
+    }
+
+    fn subsets(&self, database: &dyn Database) -> Result<Vec<InventorySet>> {
+        self.nested_subsets(database, 0)
+    }
+}
+
+/// Part list in rebrickable .csv format (columns Part, Color, Quantity, Is Spare),
+/// a bricklink wanted list in .xml format, an LDraw model in .ldr or .mpd format
+/// or an LDD model in .lxf or .lxfml format.
+pub struct FileInventory {
+    path: PathBuf,
+}
+
+impl FileInventory {
+    pub fn new(path: &str) -> FileInventory {
+        FileInventory {
+            path: PathBuf::from(path),
+        }
+    }
+}
+
+impl InventoryLoader for FileInventory {
+    fn name(&self) -> String {
+        // file name without any extension
+        self.path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.split('.').next())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn title(&self) -> String {
+        self.name()
+    }
+
+    fn load(&self, database: &dyn Database, options: &LoadOptions) -> Result<Vec<InventoryPart>> {
+        if !Path::new(&self.path).exists() {
+            return Err(Error::Io {
+                path: self.path.display().to_string(),
+                source: std::io::Error::new(std::io::ErrorKind::NotFound, "file does not exist"),
+            });
+        }
+        let path = self.path.display().to_string();
+        let inventory = match self
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension) if extension.eq_ignore_ascii_case("xml") => {
+                inventory_from_bricklink_file(&path, database)
+            }
+            Some(extension)
+                if extension.eq_ignore_ascii_case("ldr")
+                    || extension.eq_ignore_ascii_case("mpd") =>
+            {
+                inventory_from_ldraw_file(&path, database)
+            }
+            Some(extension)
+                if extension.eq_ignore_ascii_case("lxf")
+                    || extension.eq_ignore_ascii_case("lxfml") =>
+            {
+                inventory_from_lxf_file(&path, database)
+            }
+            _ => inventory_from_file(&path, database),
+        }?;
+        Ok(options.filter(inventory))
+    }
+}
+
+/// Sets or parts of the rebrickable account of the user token.
+#[derive(Debug, Clone)]
+pub enum UserInventory {
+    /// All parts of the user's sets and part lists.
+    AllParts,
+    /// All sets of the user.
+    Sets,
+    /// A set list by name or id.
+    SetList(String),
+    /// A part list by name or id.
+    PartList(String),
+}
+
+impl std::str::FromStr for UserInventory {
+    type Err = String;
+
+    /// Parses `allparts`, `sets`, `setlist:{name or id}` or `partlist:{name or id}`.
+    fn from_str(collection: &str) -> std::result::Result<UserInventory, String> {
+        match collection.split_once(':') {
+            Some(("setlist", list)) if !list.is_empty() => {
+                Ok(UserInventory::SetList(list.to_string()))
+            }
+            Some(("partlist", list)) if !list.is_empty() => {
+                Ok(UserInventory::PartList(list.to_string()))
+            }
+            None if collection == "allparts" => Ok(UserInventory::AllParts),
+            None if collection == "sets" => Ok(UserInventory::Sets),
+            _ => Err(format!(
+                "expected allparts, sets, setlist:NAME or partlist:NAME, got {}",
+                collection
+            )),
+        }
+    }
+}
+
+impl UserInventory {
+    // the sets of the user or of a set list
+    fn sets(&self, database: &dyn Database) -> Result<Vec<InventorySet>> {
+        match self {
+            UserInventory::Sets => database.user_sets(None),
+            UserInventory::SetList(list) => {
+                let list_id = find_list(&database.user_set_lists()?, list, "set list")?;
+                database.user_sets(Some(list_id))
+            }
+            UserInventory::AllParts | UserInventory::PartList(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+impl InventoryLoader for UserInventory {
+    fn name(&self) -> String {
+        match self {
+            UserInventory::AllParts => "allparts".to_string(),
+            UserInventory::Sets => "sets".to_string(),
+            UserInventory::SetList(list) => format!("setlist_{}", list),
+            UserInventory::PartList(list) => format!("partlist_{}", list),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            UserInventory::AllParts => "All my parts".to_string(),
+            UserInventory::Sets => "All my sets".to_string(),
+            UserInventory::SetList(list) => format!("Set list {}", list),
+            UserInventory::PartList(list) => format!("Part list {}", list),
+        }
+    }
+
+    fn load(&self, database: &dyn Database, options: &LoadOptions) -> Result<Vec<InventoryPart>> {
+        match self {
+            UserInventory::AllParts => Ok(options.filter(database.user_parts(None)?)),
+            UserInventory::PartList(list) => {
+                let list_id = find_list(&database.user_part_lists()?, list, "part list")?;
+                Ok(options.filter(database.user_parts(Some(list_id))?))
+            }
+            UserInventory::Sets | UserInventory::SetList(_) => {
+                let mut inventories = Vec::new();
+                for set in self.sets(database)? {
+                    // e.g. sets the user added before rebrickable had their inventory
+                    let inventory = SetInventory::new(&set.set_num).load(database, options);
+                    if let Some(inventory) = skip_unknown_set(&set.set_num, inventory)? {
+                        inventories.push((set.quantity, inventory));
+                    }
+                }
+                Ok(merge_inventories(inventories))
+            }
+        }
+    }
+
+    fn minifigs(&self, database: &dyn Database) -> Result<Vec<InventoryMinifig>> {
+        let mut minifigs = Vec::new();
+        for set in self.sets(database)? {
+            let set_minifigs = SetInventory::new(&set.set_num).minifigs(database);
+            if let Some(set_minifigs) = skip_unknown_set(&set.set_num, set_minifigs)? {
+                minifigs.push((set.quantity, set_minifigs));
+            }
+        }
+        Ok(merge_minifigs(minifigs))
+    }
+
+    fn subsets(&self, database: &dyn Database) -> Result<Vec<InventorySet>> {
+        self.sets(database)
+    }
+}
+
+// id of the list with this id or name, names are compared ignoring case
+fn find_list(lists: &[UserList], list: &str, kind: &str) -> Result<i32> {
+    lists
+        .iter()
+        .find(|user_list| {
+            user_list.id.to_string() == list || user_list.name.eq_ignore_ascii_case(list)
+        })
+        .map(|user_list| user_list.id)
+        .ok_or_else(|| Error::NotFound {
+            url: format!("{} {}", kind, list),
+        })
+}
+
+/// Several inventories combined into one, each counted a number of times,
+/// e.g. three copies of a set and a part list.
+#[derive(Default)]
+pub struct MergedInventory {
+    loaders: Vec<(i32, Box<dyn InventoryLoader>)>,
+}
+
+impl MergedInventory {
+    pub fn new() -> MergedInventory {
+        MergedInventory::default()
+    }
+
+    /// Adds `count` copies of an inventory.
+    pub fn add(&mut self, count: i32, loader: Box<dyn InventoryLoader>) {
+        self.loaders.push((count, loader));
+    }
+
+    pub fn with(mut self, count: i32, loader: Box<dyn InventoryLoader>) -> MergedInventory {
+        self.add(count, loader);
+        self
+    }
+}
+
+impl InventoryLoader for MergedInventory {
+    fn name(&self) -> String {
+        // e.g. "3x10698-1+375-2"
+        self.loaders
+            .iter()
+            .map(|(count, loader)| match count {
+                1 => loader.name(),
+                _ => format!("{}x{}", count, loader.name()),
+            })
+            .collect::<Vec<String>>()
+            .join("+")
+    }
+
+    fn title(&self) -> String {
+        // e.g. "3x Set 10698-1 + Set 375-2"
+        self.loaders
+            .iter()
+            .map(|(count, loader)| match count {
+                1 => loader.title(),
+                _ => format!("{}x {}", count, loader.title()),
+            })
+            .collect::<Vec<String>>()
+            .join(" + ")
+    }
+
+    fn load(&self, database: &dyn Database, options: &LoadOptions) -> Result<Vec<InventoryPart>> {
+        let mut inventories = Vec::new();
+        for (count, loader) in &self.loaders {
+            inventories.push((*count, loader.load(database, options)?));
+        }
+        Ok(merge_inventories(inventories))
+    }
+
+    fn minifigs(&self, database: &dyn Database) -> Result<Vec<InventoryMinifig>> {
+        let mut minifigs = Vec::new();
+        for (count, loader) in &self.loaders {
+            minifigs.push((*count, loader.minifigs(database)?));
+        }
+        Ok(merge_minifigs(minifigs))
+    }
+
+    fn subsets(&self, database: &dyn Database) -> Result<Vec<InventorySet>> {
+        let mut merged: Vec<InventorySet> = Vec::new();
+        for (count, loader) in &self.loaders {
+            for subset in loader.subsets(database)? {
+                let quantity = subset.quantity * count;
+                match merged
+                    .iter_mut()
+                    .find(|merged_subset| merged_subset.set_num == subset.set_num)
+                {
+                    Some(merged_subset) => merged_subset.quantity += quantity,
+                    None => merged.push(InventorySet { quantity, ..subset }),
+                }
+            }
+        }
+        Ok(merged)
+    }
+}
+
+// sum the quantities of the minifigs per minifig, every list multiplied by its count
+fn merge_minifigs(minifigs: Vec<(i32, Vec<InventoryMinifig>)>) -> Vec<InventoryMinifig> {
+    let mut merged: Vec<InventoryMinifig> = Vec::new();
+    for (count, minifigs) in minifigs {
+        for minifig in minifigs {
+            let quantity = minifig.quantity * count;
+            match merged
+                .iter_mut()
+                .find(|merged_minifig| merged_minifig.fig_num == minifig.fig_num)
+            {
+                Some(merged_minifig) => merged_minifig.quantity += quantity,
+                None => merged.push(InventoryMinifig {
+                    quantity,
+                    ..minifig
+                }),
+            }
+        }
+    }
+    merged
+}
+
+/// Sums the quantities of all inventories per part, color and spare flag,
+/// every inventory multiplied by its count. Parts keep the order they first appear in.
+pub fn merge_inventories(
+    inventories: impl IntoIterator<Item = (i32, Vec<InventoryPart>)>,
+) -> Vec<InventoryPart> {
+    let mut merged: Vec<InventoryPart> = Vec::new();
+    let mut index: HashMap<(String, i32, bool), usize> = HashMap::new();
+    for (count, inventory_parts) in inventories {
+        for inventory_part in inventory_parts {
+            let key = (
+                inventory_part.part_num.clone(),
+                inventory_part.color_id,
+                inventory_part.is_spare,
+            );
+            match index.get(&key) {
+                Some(position) => merged[*position].quantity += inventory_part.quantity * count,
+                None => {
+                    index.insert(key, merged.len());
+                    merged.push(InventoryPart {
+                        quantity: inventory_part.quantity * count,
+                        ..inventory_part
+                    });
+                }
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Cache, CacheMode, FixtureTransport, Rebrickable};
+    use std::time::Duration;
+
+    const BASE_URL: &str = "http://fixtures/api/v3/";
+
+    // rebrickable client answering only from fixtures, everything else is not found
+    fn rebrickable(transport: FixtureTransport) -> Rebrickable {
+        let cache = Cache::new("unused", Duration::ZERO, CacheMode::Disabled);
+        Rebrickable::new("key".to_string(), cache)
+            .with_base_url(BASE_URL)
+            .with_transport(Box::new(transport))
+    }
+
+    fn set_parts_url(set_num: &str) -> String {
+        format!(
+            "{}lego/sets/{}/parts/?page_size=500&ordering=color&key=key&inc_minifig_parts=1",
+            BASE_URL, set_num
+        )
+    }
+
+    fn set_parts_without_minifigs_url(set_num: &str) -> String {
+        format!(
+            "{}lego/sets/{}/parts/?page_size=500&ordering=color&key=key&inc_minifig_parts=0",
+            BASE_URL, set_num
+        )
+    }
+
+    fn minifigs_url(set_num: &str) -> String {
+        format!(
+            "{}lego/sets/{}/minifigs/?page_size=500&key=key",
+            BASE_URL, set_num
+        )
+    }
+
+    fn subsets_url(set_num: &str) -> String {
+        format!(
+            "{}lego/sets/{}/sets/?page_size=500&key=key",
+            BASE_URL, set_num
+        )
+    }
+
+    #[test]
+    fn merged_inventories_are_multiplied_by_their_count() {
+        let merged = merge_inventories([
+            (
+                2,
+                vec![
+                    InventoryPart::new("3001".to_string(), 4, 1, false),
+                    InventoryPart::new("3001".to_string(), 4, 1, true),
+                ],
+            ),
+            (
+                3,
+                vec![
+                    InventoryPart::new("3002".to_string(), 1, 1, false),
+                    InventoryPart::new("3001".to_string(), 4, 2, false),
+                ],
+            ),
+        ]);
+        let merged: Vec<(&str, i32, i32, bool)> = merged
+            .iter()
+            .map(|part| {
+                (
+                    part.part_num.as_str(),
+                    part.color_id,
+                    part.quantity,
+                    part.is_spare,
+                )
+            })
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("3001", 4, 8, false),
+                ("3001", 4, 2, true),
+                ("3002", 1, 3, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn spares_are_only_kept_when_requested() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            &set_parts_url("6000-1"),
+            200,
+            r#"{"next": null, "results": [
+                {"part": {"part_num": "3001"}, "color": {"id": 4}, "quantity": 2, "is_spare": false},
+                {"part": {"part_num": "3001"}, "color": {"id": 4}, "quantity": 1, "is_spare": true}
+            ]}"#,
+        );
+        let database = rebrickable(transport);
+        let set = SetInventory::new("6000-1");
+
+        let inventory = set.load(&database, &LoadOptions::default()).unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert!(!inventory[0].is_spare);
+
+        let options = LoadOptions {
+            spares: true,
+            ..LoadOptions::default()
+        };
+        let inventory = set.load(&database, &options).unwrap();
+        assert_eq!(inventory.len(), 2);
+        assert!(inventory[1].is_spare);
+    }
+
+    #[test]
+    fn whole_minifigs_leave_out_their_parts() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            &set_parts_without_minifigs_url("6000-1"),
+            200,
+            r#"{"next": null, "results": [
+                {"part": {"part_num": "3001"}, "color": {"id": 4}, "quantity": 2, "is_spare": false}
+            ]}"#,
+        );
+        transport.insert(
+            &set_parts_without_minifigs_url("6001-1"),
+            200,
+            r#"{"next": null, "results": []}"#,
+        );
+        transport.insert(
+            &minifigs_url("6001-1"),
+            200,
+            r#"{"next": null, "results": [
+                {"set_num": "fig-000001", "set_name": "Knight", "quantity": 3}
+            ]}"#,
+        );
+        let database = rebrickable(transport);
+        let options = LoadOptions {
+            minifigs: MinifigMode::Whole,
+            ..LoadOptions::default()
+        };
+
+        let inventory = SetInventory::new("6000-1")
+            .load(&database, &options)
+            .unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert_eq!(inventory[0].part_num, "3001");
+        // a minifig pack has no other parts, but still an inventory
+        let inventory = SetInventory::new("6001-1")
+            .load(&database, &options)
+            .unwrap();
+        assert!(inventory.is_empty());
+    }
+
+    #[test]
+    fn bundle_skips_subsets_without_inventory() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            &subsets_url("66523-1"),
+            200,
+            r#"{"next": null, "results": [
+                {"set_num": "6000-1", "set_name": "Bundled", "quantity": 2},
+                {"set_num": "850425-1", "set_name": "Promo", "quantity": 1},
+                {"set_num": "7000-1", "set_name": "Unknown", "quantity": 1}
+            ]}"#,
+        );
+        transport.insert(
+            &set_parts_url("6000-1"),
+            200,
+            r#"{"next": null, "results": [
+                {"part": {"part_num": "3001"}, "color": {"id": 4}, "quantity": 2, "is_spare": false}
+            ]}"#,
+        );
+        let database = rebrickable(transport);
+
+        let inventory = SetInventory::new("66523-1")
+            .load(&database, &LoadOptions::default())
+            .unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert_eq!(inventory[0].part_num, "3001");
+        assert_eq!(inventory[0].quantity, 4);
+    }
+}
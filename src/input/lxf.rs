@@ -0,0 +1,190 @@
+//! LEGO Digital Designer models, .lxf archives or the .lxfml document inside them
+use super::error::{Error, Result};
+use super::{merge_inventories, Database, InventoryPart};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+// name of lego in the external ids of parts and colors, whose design and material ids ldd uses
+const CATALOG: &str = "LEGO";
+
+/// Reads an LDD model in .lxf or .lxfml format and maps the LEGO design and material ids to
+/// rebrickable parts and colors. Bricks in unknown materials are skipped.
+pub fn inventory_from_lxf_file(path: &str, database: &dyn Database) -> Result<Vec<InventoryPart>> {
+    let invalid = |message: String| Error::InvalidFile {
+        path: path.to_string(),
+        message,
+    };
+    let is_archive = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("lxf"));
+    let lxfml = if is_archive {
+        read_archive(path)?
+    } else {
+        fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_string(),
+            source,
+        })?
+    };
+    let document = roxmltree::Document::parse(&lxfml).map_err(|e| invalid(e.to_string()))?;
+    if !document.root_element().has_tag_name("LXFML") {
+        return Err(invalid("not an LXFML document".to_string()));
+    }
+
+    // version 5 groups the parts of assemblies in bricks, older versions only have parts
+    let bricks: Vec<roxmltree::Node> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("Brick"))
+        .collect();
+    let mut elements: Vec<(String, i32)> = Vec::new();
+    if bricks.is_empty() {
+        for part in document
+            .descendants()
+            .filter(|node| node.has_tag_name("Part"))
+        {
+            elements.push(element(path, part, part)?);
+        }
+    } else {
+        for brick in bricks {
+            // the first part carries the main material of the brick
+            let part = brick
+                .children()
+                .find(|node| node.has_tag_name("Part"))
+                .unwrap_or(brick);
+            elements.push(element(path, brick, part)?);
+        }
+    }
+
+    let mut color_ids: HashMap<i32, i32> = HashMap::new();
+    for color in database.all_colors()? {
+        let ext_ids = color.external_ids.get(CATALOG);
+        for ext_id in ext_ids
+            .into_iter()
+            .flat_map(|ext_ids| ext_ids.ext_ids.iter().flatten())
+        {
+            color_ids.entry(*ext_id).or_insert(color.id);
+        }
+    }
+    // the database dumps have no external color ids, every brick would be skipped
+    if color_ids.is_empty() && !elements.is_empty() {
+        return Err(Error::Unsupported(
+            "the colors have no LEGO material ids, the database dumps have none, use the api"
+                .to_string(),
+        ));
+    }
+
+    let mut ext_ids: Vec<String> = elements.iter().map(|(design, _)| design.clone()).collect();
+    ext_ids.sort();
+    ext_ids.dedup();
+    let part_nums = database.part_nums_by_external_id(CATALOG, &ext_ids)?;
+    for ext_id in ext_ids
+        .iter()
+        .filter(|ext_id| !part_nums.contains_key(*ext_id))
+    {
+        println!(
+            "Warning: no rebrickable part for design id {}, keeping its number",
+            ext_id
+        );
+    }
+
+    let mut inventory_parts = Vec::new();
+    for (design, material) in elements {
+        let color_id = match color_ids.get(&material) {
+            Some(color_id) => *color_id,
+            None => {
+                println!(
+                    "Warning: skipping design id {} in unknown material {}",
+                    design, material
+                );
+                continue;
+            }
+        };
+        let part_num = part_nums.get(&design).cloned().unwrap_or(design);
+        inventory_parts.push(InventoryPart::new(part_num, color_id, 1, false));
+    }
+    Ok(merge_inventories([(1, inventory_parts)]))
+}
+
+// the .lxfml document in an .lxf archive
+fn read_archive(path: &str) -> Result<String> {
+    let invalid = |message: String| Error::InvalidFile {
+        path: path.to_string(),
+        message,
+    };
+    let file = File::open(path).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid(e.to_string()))?;
+    let name = archive
+        .file_names()
+        .find(|name| name.to_lowercase().ends_with(".lxfml"))
+        .map(|name| name.to_string())
+        .ok_or_else(|| invalid("no .lxfml document in the archive".to_string()))?;
+    let mut lxfml = String::new();
+    archive
+        .by_name(&name)
+        .map_err(|e| invalid(e.to_string()))?
+        .read_to_string(&mut lxfml)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(lxfml)
+}
+
+// design id of the brick and the first material of its part, e.g. materials="21,0"
+fn element(path: &str, brick: roxmltree::Node, part: roxmltree::Node) -> Result<(String, i32)> {
+    let invalid = |attribute: &str| Error::InvalidFile {
+        path: path.to_string(),
+        message: format!(
+            "{} in line {} has no valid {}",
+            brick.tag_name().name(),
+            brick.document().text_pos_at(brick.range().start).row,
+            attribute
+        ),
+    };
+    let design = brick
+        .attribute("designID")
+        .ok_or_else(|| invalid("designID"))?;
+    // version 4 has a single materialID
+    let material = part
+        .attribute("materials")
+        .or_else(|| part.attribute("materialID"))
+        .and_then(|materials| materials.split(',').next())
+        .and_then(|material| material.trim().parse::<i32>().ok())
+        .ok_or_else(|| invalid("material"))?;
+    Ok((design.to_string(), material))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Cache, CacheMode, FixtureTransport, Rebrickable};
+    use std::time::Duration;
+
+    #[test]
+    fn colors_without_material_ids_are_unsupported() {
+        let path =
+            std::env::temp_dir().join(format!("brickstats-lxf-dump-{}.lxfml", std::process::id()));
+        fs::write(
+            &path,
+            r#"<LXFML versionMajor="4"><Part refID="0" designID="3001" materialID="21"/></LXFML>"#,
+        )
+        .unwrap();
+        // colors like those of the database dumps, without external ids
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "http://fixtures/api/v3/lego/colors/?page_size=1000&ordering=name&key=key",
+            200,
+            r#"{"next": null, "results": [{"id": 4, "name": "Red", "rgb": "C91A09", "is_trans": false}]}"#,
+        );
+        let cache = Cache::new("unused", Duration::ZERO, CacheMode::Disabled);
+        let database = Rebrickable::new("key".to_string(), cache)
+            .with_base_url("http://fixtures/api/v3/")
+            .with_transport(Box::new(transport));
+
+        let result = inventory_from_lxf_file(&path.display().to_string(), &database);
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        fs::remove_file(path).unwrap();
+    }
+}
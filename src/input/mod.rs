@@ -1,259 +1,459 @@
+//! Loading inventories and looking up part, color and category details,
+//! from the rebrickable api, its database dumps or part list files.
+
+mod bricklink;
+mod cache;
+mod catalogue;
+mod dump;
+mod error;
+mod fixtures;
+mod http;
+mod ldraw;
+mod loader;
+mod lxf;
+mod model;
+mod part_list;
 mod rebrickable;
 
 use crate::output::{Dataset, InventoryEntry};
-pub use rebrickable::{color, inventory, inventory_part, part_category, part_details};
-use serde::{de, Deserialize};
-use std::path::Path;
+use crate::stats::Difference;
+pub use bricklink::{inventory_from_bricklink_file, inventory_to_bricklink_file};
+pub use cache::{Cache, CacheMode, CacheUsage, CACHE_DIRECTORY, DEFAULT_TTL_DAYS};
+pub use catalogue::Catalogue;
+pub use dump::RebrickableDump;
+pub use error::{Error, Result};
+pub use fixtures::{RecordingTransport, ReplayTransport};
+use http::HttpClient;
+pub use http::{FixtureTransport, ReqwestTransport, Response, Transport, DEFAULT_BASE_URL};
+pub use ldraw::inventory_from_ldraw_file;
+pub use loader::{
+    merge_inventories, FileInventory, InventoryLoader, LoadOptions, MergedInventory, MinifigMode,
+    SetInventory, UserInventory,
+};
+pub use lxf::inventory_from_lxf_file;
+pub use model::{
+    Color, ColorExternalIds, InventoryMinifig, InventoryPart, InventorySet, Part, PartCategory,
+    Set, Theme, UserList,
+};
+pub use part_list::{inventory_from_file, read_part_list, PartListReport};
+use std::collections::HashMap;
+
+/// Queries answered by every source of rebrickable data.
+pub trait Database {
+    /// Parts of a set (`12345-1`), minifigs dissolved into their parts, spares flagged as such.
+    fn inventory(&self, set_num: &str) -> Result<Vec<InventoryPart>>;
+    /// Parts of a set without the parts of its minifigs, e.g. to count them as whole units.
+    fn inventory_without_minifigs(&self, set_num: &str) -> Result<Vec<InventoryPart>>;
+    /// Minifigs of a set as whole units, e.g. to count them instead of their parts.
+    fn minifigs(&self, set_num: &str) -> Result<Vec<InventoryMinifig>>;
+    /// Parts of a minifig (`fig-000123`).
+    fn minifig_inventory(&self, fig_num: &str) -> Result<Vec<InventoryPart>>;
+    /// Sets contained in a set, e.g. in a bundle, whose parts are not part of its inventory.
+    fn subsets(&self, set_num: &str) -> Result<Vec<InventorySet>>;
+    /// All colors known to rebrickable.
+    fn all_colors(&self) -> Result<Vec<Color>>;
+    /// All part categories known to rebrickable.
+    fn all_categories(&self) -> Result<Vec<PartCategory>>;
+    /// Details of all parts in the inventory.
+    fn parts(&self, inventory_parts: &[InventoryPart]) -> Result<Vec<Part>>;
+    /// All sets known to rebrickable.
+    fn all_sets(&self) -> Result<Vec<Set>>;
+    /// All themes known to rebrickable.
+    fn all_themes(&self) -> Result<Vec<Theme>>;
+    /// Inventories of many sets at once, like [`Database::inventory`], by set number.
+    fn inventories(&self, set_nums: &[String]) -> Result<HashMap<String, Vec<InventoryPart>>>;
+    /// Set lists of the rebrickable account of the user.
+    fn user_set_lists(&self) -> Result<Vec<UserList>>;
+    /// Part lists of the rebrickable account of the user.
+    fn user_part_lists(&self) -> Result<Vec<UserList>>;
+    /// Sets of a set list of the user, without list all sets of the user.
+    fn user_sets(&self, set_list_id: Option<i32>) -> Result<Vec<InventorySet>>;
+    /// Parts of a part list of the user, without list all parts of the user's sets and
+    /// part lists.
+    fn user_parts(&self, part_list_id: Option<i32>) -> Result<Vec<InventoryPart>>;
+    /// Part numbers of the parts known in another catalog, e.g. `BrickLink` or `LDraw`,
+    /// by their id there. Unknown ids are left out.
+    ///
+    /// The database dumps have no external ids, [`RebrickableDump`] maps every id that is a
+    /// known rebrickable part number to itself.
+    fn part_nums_by_external_id(
+        &self,
+        catalog: &str,
+        ext_ids: &[String],
+    ) -> Result<HashMap<String, String>>;
+}
 
+/// Client for the rebrickable api v3, caching all responses on disk.
 pub struct Rebrickable {
     api_token: String,
+    // token of a rebrickable account for its collections, see Rebrickable::login
+    user_token: Option<String>,
+    cache: Cache,
+    // shared by all requests, so that the rate limit applies across endpoints
+    client: HttpClient,
 }
 
 impl Rebrickable {
-    pub fn new(api_token: String) -> Rebrickable {
-        Rebrickable { api_token }
+    /// Client for rebrickable.com using the api token of a rebrickable account.
+    pub fn new(api_token: String, cache: Cache) -> Rebrickable {
+        Rebrickable {
+            api_token,
+            user_token: None,
+            cache,
+            client: HttpClient::new(http::REQUESTS_PER_SECOND, http::BURST, http::MAX_ATTEMPTS),
+        }
+    }
+
+    /// Sends requests to another server than rebrickable.com, e.g. a mirror.
+    pub fn with_base_url(mut self, base_url: &str) -> Rebrickable {
+        self.client.set_base_url(base_url);
+        self
     }
 
-    pub fn inventory(&self, set_num: &str) -> Vec<inventory_part> {
-        // download set inventory
-        let inventory = inventory::new(set_num);
-        inventory.download(&self.api_token, false)
+    /// Sends requests through another transport, e.g. in-memory fixtures.
+    pub fn with_transport(mut self, transport: Box<dyn Transport>) -> Rebrickable {
+        self.client.set_transport(transport);
+        self
     }
 
-    pub fn all_colors(&self) -> Vec<color> {
-        color::get_all_cached(&self.api_token)
+    /// Accesses the collections of the rebrickable account this user token belongs to.
+    pub fn with_user_token(mut self, user_token: String) -> Rebrickable {
+        self.user_token = Some(user_token);
+        self
     }
 
-    pub fn all_categories(&self) -> Vec<part_category> {
-        part_category::get_all_cached(&self.api_token)
+    /// Logs in to a rebrickable account, returns the user token for [`Rebrickable::with_user_token`].
+    pub fn login(&self, username: &str, password: &str) -> Result<String> {
+        rebrickable::get_user_token(username, password, &self.client, &self.api_token)
     }
 
-    pub fn part_details(&self, inventory_parts: &[inventory_part]) -> Vec<part_details> {
-        // get all part details for the parts in the inventory
-        let all_part_details = part_details::get_many(
-            inventory_parts.iter().map(|p| p.part_num()).collect(),
-            &self.api_token,
-        );
-        println!("Got part details for {} parts", all_part_details.len());
-        all_part_details
+    fn user_token(&self) -> Result<&str> {
+        self.user_token.as_deref().ok_or_else(|| {
+            Error::Unsupported("user collections need a user token, log in first".to_string())
+        })
     }
 }
 
-pub fn prepare_dataset(
-    inventory_parts: Vec<inventory_part>,
-    part_details: Vec<part_details>,
-    categories: Vec<part_category>,
-    colors: Vec<color>,
-) -> Dataset {
-    // create new vector with (part_category_id, quantity, color_id) tuples
-    let mut data_tuples: Vec<(i32, i32, i32)> = Vec::new();
-    // for all inventory_parts
-    for inventory_part in &inventory_parts {
-        // find the part_category_id for the part by part_num
-        let part_category_id = match part_details
-            .iter()
-            .find(|part_details| part_details.part_num() == inventory_part.part_num())
-        {
-            Some(part_details) => part_details.part_cat_id(),
-            None => {
-                println!(
-                    "Error finding part_category_id for part_num {}",
-                    inventory_part.part_num()
-                );
-                0
-            }
-        };
-        data_tuples.push((
-            part_category_id,
-            inventory_part.quantity(),
-            inventory_part.color_id(),
-        ));
+impl Database for Rebrickable {
+    fn inventory(&self, set_num: &str) -> Result<Vec<InventoryPart>> {
+        // set_parts holds the whole inventory of the set, spares and the parts of minifigs
+        // included, the loaders leave out what is not wanted
+        self.cache.get_or_fetch("set_parts", set_num, || {
+            InventoryPart::get_all(set_num, true, &self.client, &self.api_token)
+        })
     }
 
-    // sort by quantity first
-    data_tuples.sort_by(|a, b| b.1.cmp(&a.1));
-    // then by color_id
-    data_tuples.sort_by(|a, b| a.2.cmp(&b.2));
+    fn inventory_without_minifigs(&self, set_num: &str) -> Result<Vec<InventoryPart>> {
+        self.cache
+            .get_or_fetch("set_parts_without_minifigs", set_num, || {
+                InventoryPart::get_all(set_num, false, &self.client, &self.api_token)
+            })
+    }
 
-    let mut data: Vec<Vec<i32>> = Vec::new();
-    let mut labels: Vec<String> = Vec::new();
-    let mut color_rgbs: Vec<String> = Vec::new();
-    let mut color_ids: Vec<i32> = Vec::new();
+    fn minifigs(&self, set_num: &str) -> Result<Vec<InventoryMinifig>> {
+        self.cache.get_or_fetch("set_minifigs", set_num, || {
+            InventoryMinifig::get_all(set_num, &self.client, &self.api_token)
+        })
+    }
 
-    // fill unique part_category_ids
-    let mut unique_part_category_ids: Vec<i32> = Vec::new();
-    for (part_category_id, _, _) in &data_tuples {
-        if !unique_part_category_ids.contains(part_category_id) {
-            unique_part_category_ids.push(*part_category_id);
-        }
+    fn minifig_inventory(&self, fig_num: &str) -> Result<Vec<InventoryPart>> {
+        self.cache.get_or_fetch("minifig_parts", fig_num, || {
+            InventoryPart::get_minifig_parts(fig_num, &self.client, &self.api_token)
+        })
     }
 
-    // sort unique_part_category_ids by category name
-    unique_part_category_ids.sort_by(|a, b| {
-        let a_name = match categories
-            .iter()
-            .find(|category_details| category_details.get_id() == *a)
-        {
-            Some(category_details) => category_details.get_name(),
-            None => {
-                println!(
-                    "Error finding part_category_name for part_category_id {}",
-                    a
-                );
-                "unknown".to_string()
-            }
-        };
-        let b_name = match categories
-            .iter()
-            .find(|category_details| category_details.get_id() == *b)
-        {
-            Some(category_details) => category_details.get_name(),
-            None => {
-                println!(
-                    "Error finding part_category_name for part_category_id {}",
-                    b
-                );
-                "unknown".to_string()
-            }
-        };
-        a_name.cmp(&b_name)
-    });
+    fn subsets(&self, set_num: &str) -> Result<Vec<InventorySet>> {
+        self.cache.get_or_fetch("set_sets", set_num, || {
+            InventorySet::get_all(set_num, &self.client, &self.api_token)
+        })
+    }
 
-    // fill data and color_ids with with datatuples values
-    for tuple in &data_tuples {
-        // get index of part_category_id in unique_part_category_ids
-        let index = unique_part_category_ids
-            .iter()
-            .position(|id| id == &tuple.0)
-            .unwrap();
+    fn all_colors(&self) -> Result<Vec<Color>> {
+        self.cache.get_or_fetch("colors", "all", || {
+            Color::get_all(&self.client, &self.api_token)
+        })
+    }
 
-        // get len of unique_part_category_ids
-        let len = unique_part_category_ids.len();
+    fn all_categories(&self) -> Result<Vec<PartCategory>> {
+        self.cache.get_or_fetch("part_categories", "all", || {
+            PartCategory::get_all(&self.client, &self.api_token)
+        })
+    }
 
-        // create new data vector
-        let mut new_data: Vec<i32> = vec![0; len];
+    fn parts(&self, inventory_parts: &[InventoryPart]) -> Result<Vec<Part>> {
+        // unique part numbers of the inventory
+        let mut part_nums: Vec<String> = inventory_parts
+            .iter()
+            .map(|inventory_part| inventory_part.part_num.clone())
+            .collect();
+        part_nums.sort();
+        part_nums.dedup();
 
-        // set quantity at index
-        new_data[index] = tuple.1;
+        // get all part details for the parts in the inventory, only parts not seen before are downloaded
+        let parts = self.cache.get_many_or_fetch(
+            "parts",
+            &part_nums,
+            |missing| Part::get_many(missing, &self.client, &self.api_token),
+            |part| part.part_num.clone(),
+        )?;
+        println!("Got part details for {} parts", parts.len());
+        Ok(parts)
+    }
 
-        // push new_data to data
-        data.push(new_data);
+    fn all_sets(&self) -> Result<Vec<Set>> {
+        self.cache.get_or_fetch("sets", "all", || {
+            Set::get_all(&self.client, &self.api_token)
+        })
+    }
 
-        // push color_id to color_ids
-        color_ids.push(tuple.2);
+    fn all_themes(&self) -> Result<Vec<Theme>> {
+        self.cache.get_or_fetch("themes", "all", || {
+            Theme::get_all(&self.client, &self.api_token)
+        })
     }
 
-    // replace unique_part_category_ids with names
-    for part_category_id in &unique_part_category_ids {
-        let part_category_name = match categories
-            .iter()
-            .find(|category_details| category_details.get_id() == *part_category_id)
-        {
-            Some(category_details) => category_details.get_name(),
-            None => {
-                println!(
-                    "Error finding part_category_name for part_category_id {}",
-                    part_category_id
-                );
-                "unknown".to_string()
+    fn inventories(&self, set_nums: &[String]) -> Result<HashMap<String, Vec<InventoryPart>>> {
+        // one request per set, every inventory is cached on its own
+        let mut inventories = HashMap::new();
+        for (index, set_num) in set_nums.iter().enumerate() {
+            if index % 100 == 0 {
+                println!("Getting inventories {} of {}", index + 1, set_nums.len());
             }
-        };
-        labels.push(part_category_name);
+            match self.inventory(set_num) {
+                Ok(inventory_parts) => {
+                    inventories.insert(set_num.clone(), inventory_parts);
+                }
+                // e.g. promotional sets with letters in their number
+                Err(Error::InvalidSetNumber(_)) => {
+                    println!("Warning: skipping set {} with unsupported number", set_num)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(inventories)
     }
 
-    // replace color_ids with rgb values
-    for id in color_ids {
-        let rgb = match colors.iter().find(|color| color.id() == id) {
-            Some(color) => color.rgb(),
-            None => {
-                println!("Error finding rgb for color_id {}", id);
-                "000000".to_string()
+    // user collections change often, they are only read from the cache when offline
+    fn user_set_lists(&self) -> Result<Vec<UserList>> {
+        let user_token = self.user_token()?;
+        self.cache.fetch_and_store("user_setlists", "all", || {
+            UserList::get_set_lists(user_token, &self.client, &self.api_token)
+        })
+    }
+
+    fn user_part_lists(&self) -> Result<Vec<UserList>> {
+        let user_token = self.user_token()?;
+        self.cache.fetch_and_store("user_partlists", "all", || {
+            UserList::get_part_lists(user_token, &self.client, &self.api_token)
+        })
+    }
+
+    fn user_sets(&self, set_list_id: Option<i32>) -> Result<Vec<InventorySet>> {
+        let user_token = self.user_token()?;
+        let key = set_list_id.map_or("all".to_string(), |list_id| list_id.to_string());
+        self.cache.fetch_and_store("user_sets", &key, || {
+            InventorySet::get_user_sets(user_token, set_list_id, &self.client, &self.api_token)
+        })
+    }
+
+    fn user_parts(&self, part_list_id: Option<i32>) -> Result<Vec<InventoryPart>> {
+        let user_token = self.user_token()?;
+        let key = part_list_id.map_or("all".to_string(), |list_id| list_id.to_string());
+        self.cache.fetch_and_store("user_parts", &key, || {
+            InventoryPart::get_user_parts(user_token, part_list_id, &self.client, &self.api_token)
+        })
+    }
+
+    fn part_nums_by_external_id(
+        &self,
+        catalog: &str,
+        ext_ids: &[String],
+    ) -> Result<HashMap<String, String>> {
+        // the api filters by one external id per request
+        let endpoint = format!("{}_parts", catalog.to_lowercase());
+        let mut part_nums = HashMap::new();
+        for ext_id in ext_ids {
+            let parts: Vec<Part> = self.cache.get_or_fetch(&endpoint, ext_id, || {
+                Part::get_by_external_id(catalog, ext_id, &self.client, &self.api_token)
+            })?;
+            if let Some(part) = parts.first() {
+                part_nums.insert(ext_id.clone(), part.part_num.clone());
             }
-        };
-        color_rgbs.push(rgb);
+        }
+        Ok(part_nums)
+    }
+}
+
+/// Builds the histogram dataset: one bin per part category, one row per inventory entry,
+/// colored like the part. Spare parts are stacked on top, hatched.
+pub fn prepare_dataset(inventory_parts: &[InventoryPart], catalogue: &Catalogue) -> Dataset {
+    // create new vector with (part_category_id, quantity, color_id, is_spare) tuples
+    let mut data_tuples: Vec<(i32, i32, i32, bool)> = Vec::new();
+    for inventory_part in inventory_parts {
+        data_tuples.push((
+            part_category_id(&inventory_part.part_num, catalogue),
+            inventory_part.quantity,
+            inventory_part.color_id,
+            inventory_part.is_spare,
+        ));
     }
 
-    Dataset::new("".to_string(), labels, data, color_rgbs)
+    // sort by quantity first
+    data_tuples.sort_by_key(|t| std::cmp::Reverse(t.1));
+    // then by color_id
+    data_tuples.sort_by_key(|t| t.2);
+    // spares last
+    data_tuples.sort_by_key(|t| t.3);
+
+    dataset_from_tuples(&data_tuples, catalogue)
 }
 
-#[derive(Debug, Deserialize)]
-struct DatasetEntry {
-    #[serde(rename = "Part")]
-    part: String,
-    #[serde(rename = "Quantity")]
-    quantity: i32,
-    #[serde(rename = "Color")]
-    color_id: i32,
-    #[serde(rename = "Is Spare", deserialize_with = "deserialize_bool", default)]
-    is_spare: Option<bool>,
+/// Builds a diverging histogram dataset of the differences between two inventories:
+/// parts added in b are stacked above the axis, parts removed below.
+pub fn prepare_comparison_dataset(differences: &[Difference], catalogue: &Catalogue) -> Dataset {
+    let mut data_tuples: Vec<(i32, i32, i32, bool)> = differences
+        .iter()
+        .map(|difference| {
+            (
+                part_category_id(&difference.part_num, catalogue),
+                difference.change(),
+                difference.color_id,
+                false,
+            )
+        })
+        .collect();
+
+    // largest changes first, then by color_id
+    data_tuples.sort_by_key(|t| std::cmp::Reverse(t.1.abs()));
+    data_tuples.sort_by_key(|t| t.2);
+
+    dataset_from_tuples(&data_tuples, catalogue)
 }
 
-fn deserialize_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
-where
-    D: de::Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    match s.as_ref() {
-        "False" => Ok(Some(false)),
-        "True" => Ok(Some(true)),
-        _ => Err(serde::de::Error::custom(format!("invalid bool: {}", s))),
+// find the part_category_id for the part by part_num, 0 if the part is unknown
+fn part_category_id(part_num: &str, catalogue: &Catalogue) -> i32 {
+    match catalogue.part(part_num) {
+        Some(part) => part.part_cat_id,
+        None => {
+            println!("Error finding part_category_id for part_num {}", part_num);
+            0
+        }
     }
 }
 
-pub fn inventory_from_file(path: &str) -> Vec<inventory_part> {
-    // check if path exists and open file
-    let path = Path::new(path);
-    if !path.exists() {
-        panic!("Error: file {} does not exist", path.display());
-    } else {
-        // read csv file with columns Part,Color,Quantity,Is Spare
-        let mut rdr = csv::Reader::from_path(path).unwrap();
+// one histogram row per (part_category_id, quantity, color_id, hatched) tuple,
+// bins sorted by category name
+fn dataset_from_tuples(data_tuples: &[(i32, i32, i32, bool)], catalogue: &Catalogue) -> Dataset {
+    // names of the unique part_category_ids
+    let mut category_names: HashMap<i32, String> = HashMap::new();
+    for (part_category_id, _, _, _) in data_tuples {
+        category_names.entry(*part_category_id).or_insert_with(|| {
+            match catalogue.category(*part_category_id) {
+                Some(category) => category.name.clone(),
+                None => {
+                    println!(
+                        "Error finding part_category_name for part_category_id {}",
+                        part_category_id
+                    );
+                    "unknown".to_string()
+                }
+            }
+        });
+    }
 
-        let mut inventory_parts = Vec::new();
+    // sort unique_part_category_ids by category name
+    let mut unique_part_category_ids: Vec<i32> = category_names.keys().copied().collect();
+    unique_part_category_ids.sort_by(|a, b| (&category_names[a], a).cmp(&(&category_names[b], b)));
+    let category_index: HashMap<i32, usize> = unique_part_category_ids
+        .iter()
+        .enumerate()
+        .map(|(index, id)| (*id, index))
+        .collect();
 
-        // read csv file into data
-        for result in rdr.deserialize() {
-            let record: DatasetEntry = result.unwrap();
+    // fill data and color_rgbs with datatuples values
+    let mut data: Vec<Vec<i32>> = Vec::new();
+    let mut color_rgbs: Vec<String> = Vec::new();
+    let mut hatched: Vec<bool> = Vec::new();
+    for (part_category_id, quantity, color_id, is_hatched) in data_tuples {
+        // quantity in the bin of the part category, zero in all others
+        let mut new_data: Vec<i32> = vec![0; unique_part_category_ids.len()];
+        new_data[category_index[part_category_id]] = *quantity;
+        data.push(new_data);
 
-            if record.is_spare == Some(true) {
-                continue;
+        let rgb = match catalogue.color(*color_id) {
+            Some(color) => color.rgb.clone(),
+            None => {
+                println!("Error finding rgb for color_id {}", color_id);
+                "000000".to_string()
             }
+        };
+        color_rgbs.push(rgb);
+        hatched.push(*is_hatched);
+    }
 
-            inventory_parts.push(inventory_part::new_simplified(
-                record.part,
-                record.color_id,
-                record.quantity,
-                true,
-            ));
-        }
-        inventory_parts
+    let labels: Vec<String> = unique_part_category_ids
+        .iter()
+        .map(|id| category_names[id].clone())
+        .collect();
+
+    Dataset::new("".to_string(), labels, data, color_rgbs).with_hatched_rows(hatched)
+}
+
+/// Writes a part list in rebrickable .csv format, which [`inventory_from_file`] reads back.
+pub fn inventory_to_file(inventory_parts: &[InventoryPart], path: &str) -> Result<()> {
+    let csv_error = |source| Error::Csv {
+        path: path.to_string(),
+        source,
+    };
+    let mut writer = csv::Writer::from_path(path).map_err(csv_error)?;
+    writer
+        .write_record(["Part", "Color", "Quantity", "Is Spare"])
+        .map_err(csv_error)?;
+    for inventory_part in inventory_parts {
+        writer
+            .write_record([
+                inventory_part.part_num.clone(),
+                inventory_part.color_id.to_string(),
+                inventory_part.quantity.to_string(),
+                if inventory_part.is_spare {
+                    "True"
+                } else {
+                    "False"
+                }
+                .to_string(),
+            ])
+            .map_err(csv_error)?;
     }
+    writer.flush().map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })
 }
 
+/// Inventory entries with color and part names, as input for [`crate::output::wordcloud`].
 pub fn formatted_inventory(
-    inventory_parts: &[inventory_part],
-    part_details: &[part_details],
-    colors: &[color],
+    inventory_parts: &[InventoryPart],
+    catalogue: &Catalogue,
 ) -> Vec<InventoryEntry> {
     // create inventory entries for all inventory parts
+    // parts and colors unknown to rebrickable, e.g. from model files, keep their ids
     let mut inventory_entries: Vec<InventoryEntry> = Vec::new();
     for inventory_part in inventory_parts {
-        let part_details = part_details
-            .iter()
-            .find(|part_details| part_details.part_num() == inventory_part.part_num())
-            .unwrap();
+        let part_name = catalogue
+            .part(&inventory_part.part_num)
+            .map(|part| part.name.clone())
+            .unwrap_or_else(|| inventory_part.part_num.clone());
 
         // find color name in colors
-        let color_name = colors
-            .iter()
-            .find(|color| color.id() == inventory_part.color_id())
-            .unwrap()
-            .name();
+        let color_name = catalogue
+            .color(inventory_part.color_id)
+            .map(|color| color.name.clone())
+            .unwrap_or_else(|| inventory_part.color_id.to_string());
 
         inventory_entries.push(InventoryEntry::new(
             color_name,
-            part_details.name(),
-            inventory_part.quantity(),
+            part_name,
+            inventory_part.quantity,
         ));
     }
     inventory_entries
@@ -0,0 +1,229 @@
+//! Rebrickable objects as returned by the api v3 <https://rebrickable.com/api/v3/docs/>,
+//! also read from the csv database dumps, whose columns use the same names.
+use serde::{de, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A color, e.g. `4` Red.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Color {
+    pub id: i32,
+    pub name: String,
+    /// Hex rgb value without `#`, e.g. `C91A09`.
+    pub rgb: String,
+    #[serde(deserialize_with = "deserialize_flag")]
+    pub is_trans: bool,
+    /// Ids of the color in other catalogs by catalog name, e.g. `BrickLink` or `LDraw`.
+    #[serde(default)]
+    pub external_ids: BTreeMap<String, ColorExternalIds>,
+}
+
+/// Ids of a color in another catalog and their names there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorExternalIds {
+    #[serde(default)]
+    pub ext_ids: Vec<Option<i32>>,
+    #[serde(default)]
+    pub ext_descrs: Vec<Vec<String>>,
+}
+
+/// A part category, e.g. `11` Bricks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartCategory {
+    pub id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub part_count: i32,
+}
+
+/// A part with its details, e.g. `3001` Brick 2 x 4.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Part {
+    pub part_num: String,
+    pub name: String,
+    pub part_cat_id: i32,
+    /// Years of the first and last set the part appears in.
+    #[serde(default)]
+    pub year_from: i32,
+    #[serde(default)]
+    pub year_to: i32,
+    #[serde(default)]
+    pub part_url: String,
+    #[serde(default)]
+    pub part_img_url: Option<String>,
+    /// Printed versions of this part.
+    #[serde(default)]
+    pub prints: Vec<String>,
+    /// Other molds of this part, which are interchangeable.
+    #[serde(default)]
+    pub molds: Vec<String>,
+    /// Parts that can be used instead of this one.
+    #[serde(default)]
+    pub alternates: Vec<String>,
+    /// The unprinted part, if this is a print.
+    #[serde(default)]
+    pub print_of: Option<String>,
+    /// Ids of the part in other catalogs by catalog name, e.g. `BrickLink` or `LDraw`.
+    #[serde(default)]
+    pub external_ids: BTreeMap<String, Vec<String>>,
+}
+
+/// A part in a given color and quantity, as contained in an inventory.
+///
+/// The api nests the part and color objects, only their ids are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "NestedInventoryPart", into = "NestedInventoryPart")]
+pub struct InventoryPart {
+    pub part_num: String,
+    pub color_id: i32,
+    pub quantity: i32,
+    pub is_spare: bool,
+}
+
+// inventory part as returned by the api, reduced to the ids of the nested objects
+#[derive(Serialize, Deserialize)]
+struct NestedInventoryPart {
+    part: PartRef,
+    color: ColorRef,
+    quantity: i32,
+    // part lists of users have no spares
+    #[serde(default)]
+    is_spare: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartRef {
+    part_num: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ColorRef {
+    id: i32,
+}
+
+impl From<NestedInventoryPart> for InventoryPart {
+    fn from(nested: NestedInventoryPart) -> InventoryPart {
+        InventoryPart {
+            part_num: nested.part.part_num,
+            color_id: nested.color.id,
+            quantity: nested.quantity,
+            is_spare: nested.is_spare,
+        }
+    }
+}
+
+impl From<InventoryPart> for NestedInventoryPart {
+    fn from(inventory_part: InventoryPart) -> NestedInventoryPart {
+        NestedInventoryPart {
+            part: PartRef {
+                part_num: inventory_part.part_num,
+            },
+            color: ColorRef {
+                id: inventory_part.color_id,
+            },
+            quantity: inventory_part.quantity,
+            is_spare: inventory_part.is_spare,
+        }
+    }
+}
+
+impl InventoryPart {
+    pub fn new(part_num: String, color_id: i32, quantity: i32, is_spare: bool) -> InventoryPart {
+        InventoryPart {
+            part_num,
+            color_id,
+            quantity,
+            is_spare,
+        }
+    }
+}
+
+/// A minifig in a given quantity, as contained in the inventory of a set,
+/// e.g. `fig-000123` Police Officer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryMinifig {
+    // the api lists the minifigs of a set like sets
+    #[serde(alias = "set_num")]
+    pub fig_num: String,
+    #[serde(default, alias = "set_name")]
+    pub name: String,
+    pub quantity: i32,
+}
+
+/// A set in a given quantity, as contained in the inventory of another set,
+/// e.g. the sets of a bundle or super pack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventorySet {
+    pub set_num: String,
+    #[serde(default, alias = "set_name")]
+    pub name: String,
+    pub quantity: i32,
+}
+
+/// An official set, e.g. `10698-1` LEGO Large Creative Brick Box.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Set {
+    pub set_num: String,
+    pub name: String,
+    pub year: i32,
+    pub theme_id: i32,
+    pub num_parts: i32,
+    // the dumps call it img_url
+    #[serde(default, alias = "img_url")]
+    pub set_img_url: Option<String>,
+    #[serde(default)]
+    pub set_url: String,
+    #[serde(default)]
+    pub last_modified_dt: String,
+}
+
+/// A set list or part list of a rebrickable account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserList {
+    pub id: i32,
+    pub name: String,
+    /// Number of sets or parts in the list.
+    #[serde(default, alias = "num_sets", alias = "num_parts")]
+    pub size: i32,
+}
+
+/// A theme, e.g. `158` Star Wars, nested in its parent theme if any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theme {
+    pub id: i32,
+    pub name: String,
+    pub parent_id: Option<i32>,
+}
+
+// the api and our own csv files use true/false, the database dumps t/f
+pub(super) fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    struct FlagVisitor;
+
+    impl<'de> de::Visitor<'de> for FlagVisitor {
+        type Value = bool;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a boolean or one of t, f, true, false, 1, 0")
+        }
+
+        fn visit_bool<E: de::Error>(self, v: bool) -> Result<bool, E> {
+            Ok(v)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<bool, E> {
+            Ok(v != 0)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<bool, E> {
+            match v.to_lowercase().as_str() {
+                "t" | "true" | "1" => Ok(true),
+                "f" | "false" | "0" => Ok(false),
+                _ => Err(E::custom(format!("invalid bool: {}", v))),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(FlagVisitor)
+}
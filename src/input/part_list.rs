@@ -0,0 +1,348 @@
+//! Part lists and set lists in .csv format, as exported by rebrickable and other tools
+use super::error::{Error, Result};
+use super::{
+    merge_inventories, Database, InventoryLoader, InventoryPart, LoadOptions, SetInventory,
+};
+
+// accepted header names, compared without case, spaces, underscores and dashes
+const PART_COLUMNS: [&str; 4] = ["part", "partnum", "partnumber", "partno"];
+const COLOR_COLUMNS: [&str; 4] = ["color", "colorid", "colour", "colourid"];
+const QUANTITY_COLUMNS: [&str; 4] = ["quantity", "qty", "count", "amount"];
+const SPARE_COLUMNS: [&str; 3] = ["isspare", "spare", "spares"];
+const SET_COLUMNS: [&str; 4] = ["set", "setnum", "setnumber", "setno"];
+
+/// What happened while reading a part list, for a summary instead of failing on the first line.
+#[derive(Debug, Clone, Default)]
+pub struct PartListReport {
+    /// Lines with a valid part or set.
+    pub rows: usize,
+    /// Rows merged into an earlier row of the same part, color and spare flag.
+    pub duplicates: usize,
+    /// Rows of spare parts.
+    pub spares: usize,
+    /// Line number and reason of every line that was skipped.
+    pub errors: Vec<(u64, String)>,
+}
+
+impl PartListReport {
+    /// Prints a one line summary and the skipped lines.
+    pub fn print(&self, path: &str) {
+        let mut summary = format!("Read {} rows from {}", self.rows, path);
+        if self.duplicates > 0 {
+            summary.push_str(&format!(", merged {} duplicate rows", self.duplicates));
+        }
+        if self.spares > 0 {
+            summary.push_str(&format!(", {} of them spares", self.spares));
+        }
+        if !self.errors.is_empty() {
+            summary.push_str(&format!(", skipped {} invalid lines", self.errors.len()));
+        }
+        println!("{}", summary);
+        for (line, message) in &self.errors {
+            println!("  line {}: {}", line, message);
+        }
+    }
+}
+
+// positions of the known columns in a part list
+struct PartColumns {
+    part: usize,
+    color: usize,
+    quantity: Option<usize>,
+    spare: Option<usize>,
+}
+
+// positions of the known columns in a set list
+struct SetColumns {
+    set: usize,
+    quantity: Option<usize>,
+}
+
+enum Columns {
+    Parts(PartColumns),
+    Sets(SetColumns),
+}
+
+/// Reads a part list in .csv format, skipping invalid lines, and merges rows of the same part,
+/// color and spare flag. Set lists (column `Set Number`) are read as the inventories
+/// of their sets, which are looked up in `database`.
+///
+/// Headers may differ in case and spelling, e.g. `Part`, `part_num` or `Part Number`. Without a
+/// quantity column every row counts once.
+pub fn read_part_list(
+    path: &str,
+    database: &dyn Database,
+) -> Result<(Vec<InventoryPart>, PartListReport)> {
+    let csv_error = |source| Error::Csv {
+        path: path.to_string(),
+        source,
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(csv_error)?;
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    let columns = columns(&headers).ok_or_else(|| Error::InvalidFile {
+        path: path.to_string(),
+        message: format!(
+            "no part and color or set number columns in header {}",
+            headers.iter().collect::<Vec<&str>>().join(",")
+        ),
+    })?;
+
+    let mut report = PartListReport::default();
+    let mut inventories: Vec<(i32, Vec<InventoryPart>)> = Vec::new();
+    let mut inventory_parts = Vec::new();
+    for result in reader.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|position| position.line()).unwrap_or(0);
+                report.errors.push((line, e.to_string()));
+                continue;
+            }
+        };
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(0);
+        // empty lines, e.g. at the end of hand-edited files
+        if record.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+        let row = match &columns {
+            Columns::Parts(columns) => part_row(&record, columns).map(|inventory_part| {
+                if inventory_part.is_spare {
+                    report.spares += 1;
+                }
+                inventory_parts.push(inventory_part);
+            }),
+            Columns::Sets(columns) => match set_row(&record, columns) {
+                // only unknown sets are invalid lines, failing to look them up fails the whole
+                // list instead of silently leaving sets out, e.g. when offline or rate limited,
+                // spares are kept and left out with the spare rows of the file
+                Ok((set, quantity)) => {
+                    match SetInventory::new(&set).load(
+                        database,
+                        &LoadOptions {
+                            spares: true,
+                            ..LoadOptions::default()
+                        },
+                    ) {
+                        Ok(inventory) => {
+                            inventories.push((quantity, inventory));
+                            Ok(())
+                        }
+                        Err(Error::NotFound { .. }) | Err(Error::InvalidSetNumber(_)) => {
+                            Err(format!("no inventory for set {}", set))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(message) => Err(message),
+            },
+        };
+        match row {
+            Ok(()) => report.rows += 1,
+            Err(message) => report.errors.push((line, message)),
+        }
+    }
+
+    let inventory = match columns {
+        Columns::Parts(_) => {
+            let rows = inventory_parts.len();
+            let merged = merge_inventories([(1, inventory_parts)]);
+            report.duplicates = rows - merged.len();
+            merged
+        }
+        Columns::Sets(_) => merge_inventories(inventories),
+    };
+    Ok((inventory, report))
+}
+
+/// Reads a part list or set list like [`read_part_list`] and prints its report.
+pub fn inventory_from_file(path: &str, database: &dyn Database) -> Result<Vec<InventoryPart>> {
+    let (inventory_parts, report) = read_part_list(path, database)?;
+    report.print(path);
+    Ok(inventory_parts)
+}
+
+// find the known columns, part lists take precedence
+fn columns(headers: &csv::StringRecord) -> Option<Columns> {
+    let find = |names: &[&str]| {
+        headers.iter().position(|header| {
+            let header: String = header
+                .chars()
+                .filter(|c| !matches!(c, ' ' | '_' | '-'))
+                .collect::<String>()
+                .to_lowercase();
+            names.contains(&header.as_str())
+        })
+    };
+    let quantity = find(&QUANTITY_COLUMNS);
+    if let (Some(part), Some(color)) = (find(&PART_COLUMNS), find(&COLOR_COLUMNS)) {
+        return Some(Columns::Parts(PartColumns {
+            part,
+            color,
+            quantity,
+            spare: find(&SPARE_COLUMNS),
+        }));
+    }
+    find(&SET_COLUMNS).map(|set| Columns::Sets(SetColumns { set, quantity }))
+}
+
+fn part_row(
+    record: &csv::StringRecord,
+    columns: &PartColumns,
+) -> std::result::Result<InventoryPart, String> {
+    let part_num = field(record, columns.part, "part")?;
+    let color_id = field(record, columns.color, "color")?;
+    let color_id = color_id
+        .parse::<i32>()
+        .map_err(|_| format!("invalid color {:?}", color_id))?;
+    let quantity = quantity(record, columns.quantity)?;
+    let is_spare = match columns.spare.and_then(|spare| record.get(spare)) {
+        Some(spare) => {
+            parse_bool(spare).ok_or_else(|| format!("invalid spare flag {:?}", spare))?
+        }
+        None => false,
+    };
+    Ok(InventoryPart::new(
+        part_num.to_string(),
+        color_id,
+        quantity,
+        is_spare,
+    ))
+}
+
+fn set_row(
+    record: &csv::StringRecord,
+    columns: &SetColumns,
+) -> std::result::Result<(String, i32), String> {
+    let set = field(record, columns.set, "set number")?;
+    Ok((set.to_string(), quantity(record, columns.quantity)?))
+}
+
+// a field that must not be empty
+fn field<'a>(
+    record: &'a csv::StringRecord,
+    index: usize,
+    name: &str,
+) -> std::result::Result<&'a str, String> {
+    match record.get(index) {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(format!("{} missing", name)),
+    }
+}
+
+// a positive quantity, one without quantity column
+fn quantity(record: &csv::StringRecord, index: Option<usize>) -> std::result::Result<i32, String> {
+    let index = match index {
+        Some(index) => index,
+        None => return Ok(1),
+    };
+    let quantity = field(record, index, "quantity")?;
+    match quantity.parse::<i32>() {
+        Ok(quantity) if quantity > 0 => Ok(quantity),
+        _ => Err(format!("invalid quantity {:?}", quantity)),
+    }
+}
+
+// the spellings of booleans in exports of different tools
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Cache, CacheMode, FixtureTransport, Rebrickable};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    // csv file of a test in the temp directory
+    fn write_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "brickstats-part-list-{}-{}.csv",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn rebrickable(cache: Cache) -> Rebrickable {
+        Rebrickable::new("key".to_string(), cache)
+            .with_base_url("http://fixtures/api/v3/")
+            .with_transport(Box::new(FixtureTransport::new()))
+    }
+
+    #[test]
+    fn unknown_sets_are_invalid_lines() {
+        let path = write_file("unknown", "Set Number,Quantity\n7000-1,1\n");
+        let cache = Cache::new("unused", Duration::ZERO, CacheMode::Disabled);
+        let (inventory, report) =
+            read_part_list(&path.display().to_string(), &rebrickable(cache)).unwrap();
+        assert!(inventory.is_empty());
+        assert_eq!(report.rows, 0);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn failed_lookups_fail_the_set_list() {
+        let path = write_file("offline", "Set Number,Quantity\n10698-1,1\n");
+        let directory =
+            std::env::temp_dir().join(format!("brickstats-part-list-cache-{}", std::process::id()));
+        let cache = Cache::new(
+            &directory.display().to_string(),
+            Duration::ZERO,
+            CacheMode::Offline,
+        );
+        let result = read_part_list(&path.display().to_string(), &rebrickable(cache));
+        assert!(matches!(result, Err(Error::NotCached { .. })));
+        fs::remove_file(path).unwrap();
+    }
+
+    fn columns_of(header: &str) -> Option<Columns> {
+        columns(&csv::StringRecord::from(
+            header.split(',').collect::<Vec<&str>>(),
+        ))
+    }
+
+    #[test]
+    fn headers_match_regardless_of_case_and_spelling() {
+        match columns_of("Qty,Colour_ID,Part Number,Is-Spare") {
+            Some(Columns::Parts(columns)) => {
+                assert_eq!((columns.part, columns.color), (2, 1));
+                assert_eq!(columns.quantity, Some(0));
+                assert_eq!(columns.spare, Some(3));
+            }
+            _ => panic!("expected a part list"),
+        }
+        match columns_of("BrickLink,SET_NUM") {
+            Some(Columns::Sets(columns)) => {
+                assert_eq!(columns.set, 1);
+                assert_eq!(columns.quantity, None);
+            }
+            _ => panic!("expected a set list"),
+        }
+    }
+
+    #[test]
+    fn part_columns_take_precedence_over_set_columns() {
+        assert!(matches!(
+            columns_of("set_num,part_num,color_id,quantity"),
+            Some(Columns::Parts(_))
+        ));
+        // a part without a color column is no part list
+        assert!(columns_of("part,quantity").is_none());
+    }
+}
@@ -1,636 +1,164 @@
-use serde::{Deserialize, Serialize};
-/// fetch lego set data using the rebrickable api v3
-/// https://rebrickable.com/api/v3/docs/
-use serde_json::Value;
-use std::path::Path;
-
+//! Fetch lego set data using the rebrickable api v3
+//! <https://rebrickable.com/api/v3/docs/>
+use super::error::{Error, Result};
+use super::http::HttpClient;
+use super::model::{
+    Color, InventoryMinifig, InventoryPart, InventorySet, Part, PartCategory, Set, Theme, UserList,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+// page sizes of the list endpoints, further pages are followed via their next link
+const COLOR_PAGE_SIZE: i32 = 1000;
 const CATEGORY_PAGE_SIZE: i32 = 500;
 const INVENTORY_PAGE_SIZE: i32 = 500;
-const COLOR_PATH: &str = "data/colors.csv";
-const CATEGORY_PATH: &str = "data/categories.csv";
-
-// rebrickable database objects
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct part_relationship {
-    id: i32,
-    parent_part_num: String,
-    child_part_num: String,
-    quantity: i32,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct part {
-    id: i32,
-    part_num: String,
-    name: String,
-    part_cat_id: i32,
-    part_url: String,
-    part_img_url: String,
-    external_ids: String,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct element {
-    element_id: String,
-    part_num: String,
-    color_id: i32,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct part_category {
-    id: i32,
-    name: String,
-    part_count: i32,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct color {
-    id: i32,
-    name: String,
-    rgb: String,
-    is_trans: bool,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct inventory_part {
-    id: i32,
-    set_num: String,
-    part_num: String,
-    color_id: i32,
-    quantity: i32,
-    is_spare: bool,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct inventory {
-    id: i32,
-    set_num: String,
-    version: i32,
-    inventory_url: String,
-    last_modified_dt: String,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct inventory_minifig {
-    inventory_id: i32,
-    fig_num: String,
-    quantity: i32,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct minifig {
-    fig_num: String,
-    name: String,
-    num_parts: i32,
+const PART_PAGE_SIZE: usize = 100;
+const SET_PAGE_SIZE: i32 = 1000;
+const THEME_PAGE_SIZE: i32 = 1000;
+const LIST_PAGE_SIZE: i32 = 100;
+
+// characters escaped in query values, all but the unreserved ones of rfc 3986
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// one page of a list endpoint
+#[derive(Deserialize)]
+struct Page<T> {
+    next: Option<String>,
+    results: Vec<T>,
 }
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct inventory_set {
-    inventory_id: i32,
-    set_num: String,
-    quantity: i32,
-}
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct set {
-    id: i32,
-    set_num: String,
-    name: String,
-    year: i32,
-    theme_id: i32,
-    num_parts: i32,
-    set_img_url: String,
-    set_url: String,
-    last_modified_dt: String,
+
+// a value for the query string, ids of other catalogs may contain e.g. & or spaces
+fn query_value(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_VALUE).to_string()
 }
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct theme {
-    id: i32,
-    name: String,
-    parent_id: i32,
-    theme_url: String,
-    num_sets: i32,
-    num_parts: i32,
-    theme_img_url: String,
-    last_modified_dt: String,
+
+// fetch all pages of a list endpoint, following the next links until the last page
+fn get_all_pages<T: DeserializeOwned>(client: &HttpClient, url: String) -> Result<Vec<T>> {
+    let mut results = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next {
+        let mut page: Page<T> = client.get_json(&url)?;
+        results.append(&mut page.results);
+        next = page.next;
+    }
+    Ok(results)
 }
 
-// get color rgb values for a vector of inventory_parts as a vector of
-impl color {
-    fn get_all(api_token: &str) -> Vec<color> {
-        // get list of part categories from /api/v3/lego/part_categories/
-        // http request
+impl Color {
+    pub(super) fn get_all(client: &HttpClient, api_token: &str) -> Result<Vec<Color>> {
+        // get list of colors from /api/v3/lego/colors/
         let url = format!(
-            "https://rebrickable.com/api/v3/lego/colors/?page_size={}&ordering=name&key={}",
-            1000, api_token
+            "{}?page_size={}&ordering=name&key={}",
+            client.url("lego/colors/"),
+            COLOR_PAGE_SIZE,
+            api_token
         );
-        println!("Downloading {}", url);
-        let response =
-            reqwest::blocking::get(&url).unwrap_or_else(|_| panic!("Error downloading {}", url));
-        if response.status() != 200 {
-            panic!("Error downloading {}", url);
-        }
-        let json: Value = serde_json::from_str(&response.text().unwrap()).unwrap();
-        let mut colors = Vec::new();
-        for color in json["results"].as_array().unwrap() {
-            let id = color["id"].as_i64().unwrap() as i32;
-            let name = color["name"].as_str().unwrap().to_string();
-            let is_trans = color["is_trans"].as_bool().unwrap();
-            let rgb = color["rgb"].as_str().unwrap().to_string();
-
-            colors.push(color {
-                id,
-                rgb,
-                is_trans,
-                name,
-            });
-        }
-
-        colors
-    }
-
-    // get all if not cached
-    pub fn get_all_cached(api_token: &str) -> Vec<color> {
-        // check if file exists
-        let path = Path::new(COLOR_PATH);
-        if path.exists() {
-            // read from file
-            let mut rdr = csv::Reader::from_path(path).unwrap();
-            let mut colors = Vec::new();
-            for result in rdr.deserialize() {
-                let record: color = result.unwrap();
-                colors.push(record);
-            }
-            colors
-        } else {
-            // download from rebrickable
-            let colors = color::get_all(api_token);
-            // write to file
-            let mut wtr = csv::Writer::from_path(path).unwrap();
-            for color in &colors {
-                wtr.serialize(color).unwrap();
-            }
-            wtr.flush().unwrap();
-            colors
-        }
-    }
-
-    pub fn id(&self) -> i32 {
-        self.id
-    }
-
-    pub fn rgb(&self) -> String {
-        self.rgb.clone()
-    }
-
-    pub fn name(&self) -> String {
-        self.name.clone()
+        get_all_pages(client, url)
     }
 }
 
-impl part_category {
-    fn get_all(api_token: &str) -> Vec<part_category> {
+impl PartCategory {
+    pub(super) fn get_all(client: &HttpClient, api_token: &str) -> Result<Vec<PartCategory>> {
         // get list of part categories from /api/v3/lego/part_categories/
-        // http request
         let url = format!(
-            "https://rebrickable.com/api/v3/lego/part_categories/?page_size={}&ordering=name&key={}",
-            CATEGORY_PAGE_SIZE, api_token
+            "{}?page_size={}&ordering=name&key={}",
+            client.url("lego/part_categories/"),
+            CATEGORY_PAGE_SIZE,
+            api_token
         );
-        println!("Downloading {}", url);
-        let response =
-            reqwest::blocking::get(&url).unwrap_or_else(|_| panic!("Error downloading {}", url));
-        if response.status() != 200 {
-            panic!("Error downloading {}", url);
-        }
-        let json: Value = serde_json::from_str(&response.text().unwrap()).unwrap();
-        let mut part_categories = Vec::new();
-        for category in json["results"].as_array().unwrap() {
-            let id = category["id"].as_i64().unwrap() as i32;
-            let name = category["name"].as_str().unwrap().to_string();
-            let part_count = category["part_count"].as_i64().unwrap() as i32;
-            part_categories.push(part_category {
-                id,
-                name,
-                part_count,
-            });
-        }
-        part_categories
-    }
-
-    pub fn get_all_cached(api_token: &str) -> Vec<part_category> {
-        // check if file exists
-        let path = Path::new(CATEGORY_PATH);
-        if path.exists() {
-            // read from file
-            let mut rdr = csv::Reader::from_path(path).unwrap();
-            let mut part_categories = Vec::new();
-            for result in rdr.deserialize() {
-                let record: part_category = result.unwrap();
-                part_categories.push(record);
-            }
-            part_categories
-        } else {
-            // download from rebrickable
-            let part_categories = part_category::get_all(api_token);
-            // write to file
-            let mut wtr = csv::Writer::from_path(path).unwrap();
-            for category in &part_categories {
-                wtr.serialize(category).unwrap();
-            }
-            wtr.flush().unwrap();
-            part_categories
-        }
-    }
-
-    pub fn get_id(&self) -> i32 {
-        self.id
-    }
-
-    pub fn get_name(&self) -> String {
-        self.name.clone()
+        get_all_pages(client, url)
     }
 }
 
-// set inventory operations
-impl inventory {
-    pub fn new(set_num: &str) -> inventory {
-        // check if set_num has 3 to 5 digits without leading zero followed by a dash and 1 digit
-        if !set_num.contains('-')
-            || set_num.len() < 5
-            || set_num.len() > 7
-            || set_num.starts_with('0')
-            || set_num.chars().nth(set_num.len() - 2).unwrap() != '-'
-            || set_num.chars().nth(set_num.len() - 1).unwrap() < '1'
-            || set_num.chars().nth(set_num.len() - 1).unwrap() > '9'
-        {
-            panic!("Invalid set_num: {}", set_num);
-        }
-        let inventory_url = format!(
-            "https://rebrickable.com/api/v3/lego/sets/{}/parts/",
-            set_num
+impl Set {
+    pub(super) fn get_all(client: &HttpClient, api_token: &str) -> Result<Vec<Set>> {
+        // get list of all sets from /api/v3/lego/sets/
+        let url = format!(
+            "{}?page_size={}&ordering=set_num&key={}",
+            client.url("lego/sets/"),
+            SET_PAGE_SIZE,
+            api_token
         );
-        let last_modified_dt = String::from("2020-01-01");
-        inventory {
-            id: 0,
-            set_num: String::from(set_num),
-            version: 0,
-            inventory_url,
-            last_modified_dt,
-        }
-    }
-
-    pub fn download(&self, api_token: &str, include_spares: bool) -> Vec<inventory_part> {
-        let mut inventory_parts = Vec::new();
-        let mut page = 1;
-        loop {
-            let url = format!(
-                "{}?page={}&page_size={}&ordering=color&key={}&inc_minifig_parts=1",
-                self.inventory_url, page, INVENTORY_PAGE_SIZE, api_token
-            );
-            println!("Downloading {}", url);
-
-            let response = reqwest::blocking::get(&url)
-                .unwrap_or_else(|_| panic!("Error downloading {}", url));
-            if response.status() != 200 {
-                break;
-            }
-
-            // get response body
-            let response_text = response
-                .text()
-                .unwrap_or_else(|_| panic!("Error reading {}", url));
-            let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-                Ok(v) => v,
-                Err(e) => {
-                    panic!("Error parsing response text {}: {}", response_text, e);
-                }
-            };
-            let results = response_json["results"]
-                .as_array()
-                .unwrap_or_else(|| panic!("Error parsing json {}", response_json));
-            if results.is_empty() {
-                break;
-            }
-            for result in results {
-                let part_num = result["part"]["part_num"]
-                    .as_str()
-                    .unwrap_or_else(|| panic!("Error parsing part_num {}", result))
-                    .to_string();
-                let color_id = result["color"]["id"]
-                    .as_i64()
-                    .unwrap_or_else(|| panic!("Error parsing color_id {}", result))
-                    as i32;
-                let quantity = result["quantity"]
-                    .as_i64()
-                    .unwrap_or_else(|| panic!("Error parsing quantity {}", result))
-                    as i32;
-                let is_spare = result["is_spare"]
-                    .as_bool()
-                    .unwrap_or_else(|| panic!("Error parsing is_spare {}", result));
-                if is_spare && !include_spares {
-                    continue;
-                }
-                inventory_parts.push(inventory_part {
-                    id: 0,
-                    set_num: self.set_num.clone(),
-                    part_num,
-                    color_id,
-                    quantity,
-                    is_spare,
-                });
-            }
-            page += 1;
-        }
-        inventory_parts
+        get_all_pages(client, url)
     }
 }
 
-impl part_details {
-    // get part details from rebrickable
-    #[allow(dead_code)]
-    pub fn new(part: &inventory_part, api_token: &str) -> Self {
+impl Theme {
+    pub(super) fn get_all(client: &HttpClient, api_token: &str) -> Result<Vec<Theme>> {
+        // get list of themes from /api/v3/lego/themes/
         let url = format!(
-            "https://rebrickable.com/api/v3/lego/parts/{}/?key={}",
-            part.part_num, api_token
+            "{}?page_size={}&ordering=id&key={}",
+            client.url("lego/themes/"),
+            THEME_PAGE_SIZE,
+            api_token
         );
-        println!("Downloading {}", url);
-        let response =
-            reqwest::blocking::get(&url).unwrap_or_else(|_| panic!("Error downloading {}", url));
-        if response.status() != 200 {
-            println!("response: {:#?}", response);
-            panic!("Error downloading {}", url);
-        }
-        // get response body
-        let response_text = response
-            .text()
-            .unwrap_or_else(|_| panic!("Error reading {}", url));
-        let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-            Ok(v) => v,
-            Err(e) => {
-                panic!("Error parsing response text {}: {}", response_text, e);
-            }
-        };
-        // println!("Parsing {}", response_text);
-
-        let part_details = part_details {
-            part_num: response_json["part_num"]
-                .as_str()
-                .unwrap_or_else(|| panic!("Error parsing part_num {}", response_json))
-                .to_string(),
-            name: response_json["name"]
-                .as_str()
-                .unwrap_or_else(|| panic!("Error parsing name {}", response_json))
-                .to_string(),
-            part_cat_id: response_json["part_cat_id"]
-                .as_i64()
-                .unwrap_or_else(|| panic!("Error parsing part_cat_id {}", response_json))
-                as i32,
-            year_from: response_json["year_from"]
-                .as_i64()
-                .unwrap_or_else(|| panic!("Error parsing year {}", response_json))
-                as i32,
-            year_to: response_json["year_to"]
-                .as_i64()
-                .unwrap_or_else(|| panic!("Error parsing year {}", response_json))
-                as i32,
-            part_url: response_json["part_url"]
-                .as_str()
-                .unwrap_or_else(|| panic!("Error parsing part_url {}", response_json))
-                .to_string(),
-            part_img_url: match response_json["part_img_url"] {
-                serde_json::Value::Null => None,
-                _ => Some(
-                    response_json["part_img_url"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("Error parsing part_img_url {}", response_json))
-                        .to_string(),
-                ),
-            },
-            prints: response_json["prints"]
-                .as_array()
-                .unwrap_or_else(|| panic!("Error parsing prints {}", response_json))
-                .iter()
-                .map(|print| print.to_string())
-                .collect(),
-            molds: response_json["molds"]
-                .as_array()
-                .unwrap_or_else(|| panic!("Error parsing molds {}", response_json))
-                .iter()
-                .map(|mold| mold.to_string())
-                .collect(),
-            alternates: response_json["alternates"]
-                .as_array()
-                .unwrap_or_else(|| panic!("Error parsing alternates {}", response_json))
-                .iter()
-                .map(|alternate| alternate.to_string())
-                .collect(),
-            print_of: match response_json["print_of"] {
-                serde_json::Value::Null => None,
-                _ => Some(
-                    response_json["print_of"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("Error parsing print_of {}", response_json))
-                        .to_string(),
-                ),
-            },
-        };
-        part_details
-    }
-
-    pub fn part_num(&self) -> String {
-        self.part_num.clone()
-    }
-
-    pub fn part_cat_id(&self) -> i32 {
-        self.part_cat_id
-    }
-
-    pub fn year_from(&self) -> i32 {
-        self.year_from
-    }
-
-    pub fn name(&self) -> String {
-        self.name.clone()
-    }
-
-    // print part details nicely formatted
-    #[allow(dead_code)]
-    pub fn print(&self) {
-        println!("Part: {}", self.part_num);
-        println!("Name: {}", self.name);
-        println!("Part Category: {}", self.part_cat_id);
-        println!("Year: {} - {}", self.year_from, self.year_to);
-        println!("Part URL: {}", self.part_url);
-        println!("Part Image URL: {:?}", self.part_img_url);
-        println!("Prints: {}", self.prints.join(", "));
-        println!("Molds: {}", self.molds.join(", "));
-        println!("Alternates: {}", self.alternates.join(", "));
-        println!("Print of: {:?}", self.print_of);
-    }
-
-    // get many part_details at once
-    pub fn get_many(part_numbers: Vec<String>, api_token: &str) -> Vec<part_details> {
-        // split part_numbers into chunks of 100
-        let mut part_details = Vec::new();
-        for chunk in part_numbers.chunks(100) {
-            part_details.append(&mut part_details::get_n(&chunk.to_vec(), api_token));
-        }
-        part_details
+        get_all_pages(client, url)
     }
+}
 
-    pub fn get_n(part_numbers: &Vec<String>, api_token: &str) -> Vec<part_details> {
-        println!("Getting part details for {} parts", part_numbers.len());
+impl InventoryPart {
+    // get the inventory of a set from /api/v3/lego/sets/{set_num}/parts/,
+    // with or without the parts of its minifigs
+    pub(super) fn get_all(
+        set_num: &str,
+        minifig_parts: bool,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventoryPart>> {
+        check_set_num(set_num)?;
         let url = format!(
-            "https://rebrickable.com/api/v3/lego/parts/?key={}&part_nums={}&inc_part_details=1",
+            "{}?page_size={}&ordering=color&key={}&inc_minifig_parts={}",
+            client.url(&format!("lego/sets/{}/parts/", set_num)),
+            INVENTORY_PAGE_SIZE,
             api_token,
-            part_numbers.join(",")
+            u8::from(minifig_parts)
         );
-        println!("Downloading {}", url);
-        let response =
-            reqwest::blocking::get(&url).unwrap_or_else(|_| panic!("Error downloading {}", url));
-        if response.status() != 200 {
-            println!("response: {:#?}", response);
-            panic!("Error downloading {}", url);
-        }
-        // get response body
-        let response_text = response
-            .text()
-            .unwrap_or_else(|_| panic!("Error reading {}", url));
-        let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-            Ok(v) => v,
-            Err(e) => {
-                panic!("Error parsing response text {}: {}", response_text, e);
-            }
-        };
-        // println!("Parsing {}", response_text);
-
-        let many_part_details = response_json["results"]
-            .as_array()
-            .unwrap_or_else(|| panic!("Error parsing results {}", response_json))
-            .iter()
-            .map(|result| {
-                // println!("Parsing {}", result);
-                let part_details = part_details {
-                    part_num: result["part_num"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("Error parsing part_num {}", result))
-                        .to_string(),
-                    name: result["name"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("Error parsing name {}", result))
-                        .to_string(),
-                    part_cat_id: result["part_cat_id"]
-                        .as_i64()
-                        .unwrap_or_else(|| panic!("Error parsing part_cat_id {}", result))
-                        as i32,
-                    year_from: result["year_from"]
-                        .as_i64()
-                        .unwrap_or_else(|| panic!("Error parsing year {}", result))
-                        as i32,
-                    year_to: result["year_to"]
-                        .as_i64()
-                        .unwrap_or_else(|| panic!("Error parsing year {}", result))
-                        as i32,
-                    part_url: result["part_url"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("Error parsing part_url {}", result))
-                        .to_string(),
-                    part_img_url: match result["part_img_url"] {
-                        serde_json::Value::Null => None,
-                        _ => Some(
-                            result["part_img_url"]
-                                .as_str()
-                                .unwrap_or_else(|| panic!("Error parsing part_img_url {}", result))
-                                .to_string(),
-                        ),
-                    },
-                    prints: result["prints"]
-                        .as_array()
-                        .unwrap_or_else(|| panic!("Error parsing prints {}", result))
-                        .iter()
-                        .map(|print| print.to_string())
-                        .collect(),
-                    molds: result["molds"]
-                        .as_array()
-                        .unwrap_or_else(|| panic!("Error parsing molds {}", result))
-                        .iter()
-                        .map(|mold| mold.to_string())
-                        .collect(),
-                    alternates: result["alternates"]
-                        .as_array()
-                        .unwrap_or_else(|| panic!("Error parsing alternates {}", result))
-                        .iter()
-                        .map(|alternate| alternate.to_string())
-                        .collect(),
-                    print_of: match result["print_of"] {
-                        serde_json::Value::Null => None,
-                        _ => Some(
-                            result["print_of"]
-                                .as_str()
-                                .unwrap_or_else(|| panic!("Error parsing print_of {}", result))
-                                .to_string(),
-                        ),
-                    },
-                };
-                part_details
-            })
-            .collect();
-        many_part_details
-    }
-}
-
-// part details
-#[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Clone)]
-pub struct part_details {
-    part_num: String,
-    name: String,
-    part_cat_id: i32,
-    year_from: i32,
-    year_to: i32,
-    part_url: String,
-    part_img_url: Option<String>,
-    prints: Vec<String>,
-    molds: Vec<String>,
-    alternates: Vec<String>,
-    // external_ids: String,
-    print_of: Option<String>,
-}
-
-impl inventory_part {
-    pub fn new_simplified(
-        part_num: String,
-        color_id: i32,
-        quantity: i32,
-        is_spare: bool,
-    ) -> inventory_part {
-        inventory_part {
-            id: -1,
-            set_num: "".to_string(),
-            part_num,
-            color_id,
-            quantity,
-            is_spare,
-        }
+        get_all_pages(client, url)
     }
 
-    pub fn part_num(&self) -> String {
-        self.part_num.clone()
-    }
-
-    pub fn color_id(&self) -> i32 {
-        self.color_id
+    // get the parts of a minifig from /api/v3/lego/minifigs/{fig_num}/parts/
+    pub(super) fn get_minifig_parts(
+        fig_num: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventoryPart>> {
+        let url = format!(
+            "{}?page_size={}&ordering=color&key={}",
+            client.url(&format!("lego/minifigs/{}/parts/", fig_num)),
+            INVENTORY_PAGE_SIZE,
+            api_token
+        );
+        get_all_pages(client, url)
     }
 
-    pub fn quantity(&self) -> i32 {
-        self.quantity
+    // get the parts of a part list from /api/v3/users/{user_token}/partlists/{list_id}/parts/,
+    // without list all parts of the user's sets and part lists from .../allparts/
+    pub(super) fn get_user_parts(
+        user_token: &str,
+        part_list_id: Option<i32>,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventoryPart>> {
+        let path = match part_list_id {
+            Some(list_id) => format!("users/{}/partlists/{}/parts/", user_token, list_id),
+            None => format!("users/{}/allparts/", user_token),
+        };
+        let url = format!(
+            "{}?page_size={}&key={}",
+            client.url(&path),
+            INVENTORY_PAGE_SIZE,
+            api_token
+        );
+        get_all_pages(client, url)
     }
 
-    // print nicely formatted inventory
-    #[allow(dead_code)]
-    pub fn print(inventory_parts: &Vec<inventory_part>) {
+    /// Prints the inventory as a table of part, color, quantity and spare.
+    pub fn print(inventory_parts: &[InventoryPart]) {
         let mut part_numbers = Vec::new();
         let mut part_colors = Vec::new();
         let mut part_quantities = Vec::new();
@@ -682,69 +210,282 @@ impl inventory_part {
     }
 }
 
-impl part {
-    // get part from rebrickable
-    #[allow(dead_code)]
-    pub fn get_part(part_num: &str, api_token: &str) -> part {
+impl InventoryMinifig {
+    // get the minifigs of a set from /api/v3/lego/sets/{set_num}/minifigs/
+    pub(super) fn get_all(
+        set_num: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventoryMinifig>> {
+        check_set_num(set_num)?;
         let url = format!(
-            "https://rebrickable.com/api/v3/lego/parts/{}/?key={}",
-            part_num, api_token
+            "{}?page_size={}&key={}",
+            client.url(&format!("lego/sets/{}/minifigs/", set_num)),
+            INVENTORY_PAGE_SIZE,
+            api_token
         );
-        println!("Downloading {}", url);
-        let response =
-            reqwest::blocking::get(&url).unwrap_or_else(|_| panic!("Error downloading {}", url));
-        if response.status() != 200 {
-            panic!("Error downloading {}", url);
-        }
-        // get response body
-        let response_text = response
-            .text()
-            .unwrap_or_else(|_| panic!("Error reading {}", url));
-        let response_json: serde_json::Value = match serde_json::from_str(&response_text) {
-            Ok(v) => v,
-            Err(e) => {
-                panic!("Error parsing response text {}: {}", response_text, e);
-            }
+        get_all_pages(client, url)
+    }
+}
+
+impl InventorySet {
+    // get the sets contained in a set from /api/v3/lego/sets/{set_num}/sets/
+    pub(super) fn get_all(
+        set_num: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventorySet>> {
+        check_set_num(set_num)?;
+        let url = format!(
+            "{}?page_size={}&key={}",
+            client.url(&format!("lego/sets/{}/sets/", set_num)),
+            INVENTORY_PAGE_SIZE,
+            api_token
+        );
+        get_all_pages(client, url)
+    }
+}
+
+// set of a user's set list, with the set nested like in inventories
+#[derive(Deserialize)]
+struct UserSet {
+    quantity: i32,
+    set: Set,
+}
+
+impl InventorySet {
+    // get the sets of a set list from /api/v3/users/{user_token}/setlists/{list_id}/sets/,
+    // without list all sets of the user from .../sets/
+    pub(super) fn get_user_sets(
+        user_token: &str,
+        set_list_id: Option<i32>,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<InventorySet>> {
+        let path = match set_list_id {
+            Some(list_id) => format!("users/{}/setlists/{}/sets/", user_token, list_id),
+            None => format!("users/{}/sets/", user_token),
         };
-        let part_num = response_json["part_num"]
-            .as_str()
-            .unwrap_or_else(|| panic!("Error parsing part_num {}", response_json))
-            .to_string();
-        let name = response_json["name"]
-            .as_str()
-            .unwrap_or_else(|| panic!("Error parsing name {}", response_json))
-            .to_string();
-        let part_url = response_json["part_url"]
-            .as_str()
-            .unwrap_or_else(|| panic!("Error parsing part_url {}", response_json))
-            .to_string();
-        let part_img_url = response_json["part_img_url"]
-            .as_str()
-            .unwrap_or_else(|| panic!("Error parsing part_img_url {}", response_json))
-            .to_string();
-        let external_ids = response_json["external_ids"]
-            .as_array()
-            .unwrap_or_else(|| panic!("Error parsing external_ids {}", response_json))
-            .iter()
-            .map(|external_id| {
-                external_id["external_id"]
-                    .as_str()
-                    .unwrap_or_else(|| panic!("Error parsing external_id {}", external_id))
-                    .to_string()
+        let url = format!(
+            "{}?page_size={}&key={}",
+            client.url(&path),
+            SET_PAGE_SIZE,
+            api_token
+        );
+        let user_sets: Vec<UserSet> = get_all_pages(client, url)?;
+        Ok(user_sets
+            .into_iter()
+            .map(|user_set| InventorySet {
+                set_num: user_set.set.set_num,
+                name: user_set.set.name,
+                quantity: user_set.quantity,
             })
-            .collect();
-        let part_cat_id = response_json["part_cat_id"]
-            .as_i64()
-            .unwrap_or_else(|| panic!("Error parsing part_cat_id {}", response_json))
-            as i32;
-        part {
-            id: 0,
-            part_num,
-            name,
-            part_url,
-            part_img_url,
-            external_ids,
-            part_cat_id,
+            .collect())
+    }
+}
+
+impl UserList {
+    // get the set lists of a user from /api/v3/users/{user_token}/setlists/
+    pub(super) fn get_set_lists(
+        user_token: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<UserList>> {
+        let url = format!(
+            "{}?page_size={}&key={}",
+            client.url(&format!("users/{}/setlists/", user_token)),
+            LIST_PAGE_SIZE,
+            api_token
+        );
+        get_all_pages(client, url)
+    }
+
+    // get the part lists of a user from /api/v3/users/{user_token}/partlists/
+    pub(super) fn get_part_lists(
+        user_token: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<UserList>> {
+        let url = format!(
+            "{}?page_size={}&key={}",
+            client.url(&format!("users/{}/partlists/", user_token)),
+            LIST_PAGE_SIZE,
+            api_token
+        );
+        get_all_pages(client, url)
+    }
+}
+
+// answer of /api/v3/users/_token/
+#[derive(Deserialize)]
+struct UserToken {
+    user_token: String,
+}
+
+// log in with username and password at /api/v3/users/_token/, returns the user token
+pub(super) fn get_user_token(
+    username: &str,
+    password: &str,
+    client: &HttpClient,
+    api_token: &str,
+) -> Result<String> {
+    let url = format!("{}?key={}", client.url("users/_token/"), api_token);
+    let form = [("username", username), ("password", password)];
+    match client.post_json::<UserToken>(&url, &form) {
+        Ok(token) => Ok(token.user_token),
+        // wrong credentials are answered with 401, unknown usernames with 400
+        Err(Error::Unauthorized { .. }) | Err(Error::HttpStatus { status: 400, .. }) => {
+            Err(Error::LoginFailed {
+                username: username.to_string(),
+            })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// check if set_num has 3 to 5 digits without leading zero followed by a dash and 1 digit
+fn check_set_num(set_num: &str) -> Result<()> {
+    let bytes = set_num.as_bytes();
+    if !set_num.contains('-')
+        || set_num.len() < 5
+        || set_num.len() > 7
+        || set_num.starts_with('0')
+        || bytes[bytes.len() - 2] != b'-'
+        || !(b'1'..=b'9').contains(&bytes[bytes.len() - 1])
+    {
+        return Err(Error::InvalidSetNumber(set_num.to_string()));
+    }
+    Ok(())
+}
+
+impl Part {
+    // get many parts with their details at once, one page per request
+    pub(super) fn get_many(
+        part_numbers: &[String],
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<Part>> {
+        let mut parts = Vec::new();
+        for chunk in part_numbers.chunks(PART_PAGE_SIZE) {
+            println!("Getting part details for {} parts", chunk.len());
+            // part numbers of LDraw models may contain e.g. # or spaces
+            let part_nums: Vec<String> =
+                chunk.iter().map(|part_num| query_value(part_num)).collect();
+            let url = format!(
+                "{}?key={}&part_nums={}&inc_part_details=1&page_size={}",
+                client.url("lego/parts/"),
+                api_token,
+                part_nums.join(","),
+                PART_PAGE_SIZE
+            );
+            parts.append(&mut get_all_pages(client, url)?);
         }
+        Ok(parts)
+    }
+
+    // get the parts known under an id of another catalog, e.g. bricklink_id=3001
+    pub(super) fn get_by_external_id(
+        catalog: &str,
+        ext_id: &str,
+        client: &HttpClient,
+        api_token: &str,
+    ) -> Result<Vec<Part>> {
+        let url = format!(
+            "{}?key={}&{}_id={}&page_size={}",
+            client.url("lego/parts/"),
+            api_token,
+            catalog.to_lowercase(),
+            query_value(ext_id),
+            PART_PAGE_SIZE
+        );
+        get_all_pages(client, url)
+    }
+
+    /// Prints the part details nicely formatted.
+    pub fn print(&self) {
+        println!("Part: {}", self.part_num);
+        println!("Name: {}", self.name);
+        println!("Part Category: {}", self.part_cat_id);
+        println!("Year: {} - {}", self.year_from, self.year_to);
+        println!("Part URL: {}", self.part_url);
+        println!("Part Image URL: {:?}", self.part_img_url);
+        println!("Prints: {}", self.prints.join(", "));
+        println!("Molds: {}", self.molds.join(", "));
+        println!("Alternates: {}", self.alternates.join(", "));
+        println!("Print of: {:?}", self.print_of);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::http::{self, FixtureTransport};
+
+    #[test]
+    fn all_pages_are_fetched() {
+        let first = "http://fixtures/api/v3/lego/colors/?page_size=2&key=key";
+        let second = "http://fixtures/api/v3/lego/colors/?page=2&page_size=2&key=key";
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            first,
+            200,
+            &format!(r#"{{"next": "{}", "results": [1, 2]}}"#, second),
+        );
+        transport.insert(second, 200, r#"{"next": null, "results": [3]}"#);
+        let mut client = HttpClient::new(http::REQUESTS_PER_SECOND, http::BURST, 1);
+        client.set_transport(Box::new(transport));
+
+        let results: Vec<i32> = get_all_pages(&client, first.to_string()).unwrap();
+        assert_eq!(results, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn missing_pages_fail() {
+        let first = "http://fixtures/api/v3/lego/colors/?page_size=2&key=key";
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            first,
+            200,
+            r#"{"next": "http://fixtures/api/v3/lego/colors/?page=2&page_size=2&key=key", "results": [1, 2]}"#,
+        );
+        let mut client = HttpClient::new(http::REQUESTS_PER_SECOND, http::BURST, 1);
+        client.set_transport(Box::new(transport));
+
+        let result: Result<Vec<i32>> = get_all_pages(&client, first.to_string());
+        assert!(matches!(result, Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn external_ids_are_encoded() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "http://fixtures/api/v3/lego/parts/?key=key&bricklink_id=3001%20%26%20x%231%2B2&page_size=100",
+            200,
+            r#"{"next": null, "results": [{"part_num": "3001", "name": "Brick 2 x 4", "part_cat_id": 11}]}"#,
+        );
+        let mut client = HttpClient::new(http::REQUESTS_PER_SECOND, http::BURST, 1);
+        client.set_base_url("http://fixtures/api/v3/");
+        client.set_transport(Box::new(transport));
+
+        let parts = Part::get_by_external_id("BrickLink", "3001 & x#1+2", &client, "key").unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].part_num, "3001");
+    }
+
+    #[test]
+    fn part_numbers_are_encoded() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "http://fixtures/api/v3/lego/parts/?key=key&part_nums=3001,u9%231%20b&inc_part_details=1&page_size=100",
+            200,
+            r#"{"next": null, "results": [{"part_num": "3001", "name": "Brick 2 x 4", "part_cat_id": 11}]}"#,
+        );
+        let mut client = HttpClient::new(http::REQUESTS_PER_SECOND, http::BURST, 1);
+        client.set_base_url("http://fixtures/api/v3/");
+        client.set_transport(Box::new(transport));
+
+        let part_nums = ["3001".to_string(), "u9#1 b".to_string()];
+        let parts = Part::get_many(&part_nums, &client, "key").unwrap();
+        assert_eq!(parts.len(), 1);
     }
 }
@@ -0,0 +1,30 @@
+//! Automated (lego(R)) set analysis using the rebrickable database.
+//!
+//! Inventories are loaded through an [`input::InventoryLoader`] (an official set or a part
+//! list file), details about their parts, colors and categories are looked up in a
+//! [`input::Database`] (the [`input::Rebrickable`] api client or a local
+//! [`input::RebrickableDump`]) and indexed in an [`input::Catalogue`].
+//! [`input::prepare_dataset`] turns both into a [`output::Dataset`], which is shown with
+//! gnuplot or saved as png or svg, and [`output::wordcloud`] renders a wordcloud of the parts.
+//!
+//! ```no_run
+//! use brickstats::input::{
+//!     prepare_dataset, Catalogue, InventoryLoader, LoadOptions, RebrickableDump, SetInventory,
+//! };
+//!
+//! let database = RebrickableDump::new("dump")?;
+//! let inventory = SetInventory::new("40567").load(&database, &LoadOptions::default())?;
+//! let catalogue = Catalogue::load(&database, &inventory)?;
+//! println!(
+//!     "average part year: {}",
+//!     brickstats::stats::average_part_year(&inventory, &catalogue)
+//! );
+//! let dataset = prepare_dataset(&inventory, &catalogue);
+//! dataset.output(Some("images/40567-1".to_string()), "Parts of Set 40567-1".to_string())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod collection;
+pub mod input;
+pub mod output;
+pub mod stats;
@@ -1,149 +1,1110 @@
-mod input;
-mod output;
-mod stats;
-
-use input::{formatted_inventory, inventory_from_file, prepare_dataset, Rebrickable};
-use output::wordcloud;
+use brickstats::collection::{self, Collection, SetFilter, Substitutes};
+use brickstats::input::{
+    formatted_inventory, inventory_to_bricklink_file, inventory_to_file,
+    prepare_comparison_dataset, prepare_dataset, Cache, CacheMode, Catalogue, Database, Error,
+    FileInventory, InventoryLoader, InventoryMinifig, InventoryPart, LoadOptions, MergedInventory,
+    MinifigMode, Rebrickable, RebrickableDump, RecordingTransport, ReplayTransport,
+    ReqwestTransport, SetInventory, Transport, UserInventory, CACHE_DIRECTORY, DEFAULT_BASE_URL,
+    DEFAULT_TTL_DAYS,
+};
+use brickstats::output::{gnuplot_available, wordcloud, ChartSize, Dataset};
+use brickstats::stats::{self, Difference};
+use std::collections::HashMap;
 use std::fs;
+use std::io::IsTerminal;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{self, arg};
+use clap::{self, arg, ArgAction, ArgGroup, ArgMatches, Command};
 /// A tool for generating lego related diagrams and visualizations.
 use std::fs::read_to_string;
 
+// token of the rebrickable account, written by user login
+const USER_TOKEN_FILE: &str = "secrets/user_token.txt";
+// yellow of the classic minifig, for the minifig bin of histograms
+const MINIFIG_RGB: &str = "F2CD37";
+// a century, longer expiry times are as good as none and could overflow as seconds
+const MAX_CACHE_TTL_DAYS: u64 = 36500;
+// larger charts would need gigabytes to rasterise
+const MAX_CHART_SIDE: u32 = 16384;
+// more sets than this are ranked with a warning that their inventories take long to get
+const MAX_API_RANK_SETS: usize = 100;
+
 fn main() {
-    let matches = clap::Command::new("brickstats")
+    let matches = cli().try_get_matches().unwrap_or_else(|e| {
+        // help and version are printed to stdout, everything else is a usage error
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { 1 } else { 0 });
+    });
+
+    create_directories();
+
+    match matches.subcommand() {
+        Some(("histogram", matches)) => histogram(matches),
+        Some(("wordcloud", matches)) => wordclouds(matches),
+        Some(("stats", matches)) => summaries(matches),
+        Some(("compare", matches)) => compare(matches),
+        Some(("export", matches)) => export(matches),
+        Some(("collection", matches)) => collection(matches),
+        Some(("user", matches)) => user(matches),
+        Some(("cache", matches)) => cache(matches),
+        _ => unreachable!("a subcommand is required"),
+    }
+
+    // remove temp files
+    fs::remove_dir_all("temp/").expect("failed to remove temp files");
+}
+
+fn cli() -> Command {
+    // every subcommand working on inventories takes any number of sets and files
+    let inputs = [
+        arg!(-s --set <SET> "set number in 12345-1 or 12345 format, optionally with a count like \"3x 10698-1\", can be repeated")
+            .required(false)
+            .action(ArgAction::Append),
+        arg!(-f --file <FILE> "part list in rebrickable .csv format, optionally with a count like \"2x parts.csv\", can be repeated")
+            .required(false)
+            .action(ArgAction::Append),
+        arg!(-u --user <COLLECTION> "collection of your rebrickable account: allparts, sets, setlist:NAME or partlist:NAME, can be repeated")
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(UserInventory::from_str),
+    ];
+    let merge =
+        arg!(-m --merge "combine all sets and files into a single inventory").required(false);
+    let inputs_group = ArgGroup::new("inputs")
+        .args(["set", "file", "user"])
+        .multiple(true)
+        .required(true);
+    // the parts and sets the user owns
+    let collection = [
+        arg!(--"owned-set" <SET> "owned set, optionally with a count like \"2x 10698-1\", can be repeated")
+            .required(false)
+            .action(ArgAction::Append),
+        arg!(--"owned-file" <FILE> "owned parts in rebrickable .csv format, can be repeated")
+            .required(false)
+            .action(ArgAction::Append),
+        arg!(--"owned-user" <COLLECTION> "owned collection of your rebrickable account like allparts, can be repeated")
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(UserInventory::from_str),
+    ];
+    let collection_group = ArgGroup::new("collection")
+        .args(["owned-set", "owned-file", "owned-user"])
+        .multiple(true)
+        .required(true);
+    let format = arg!(--format <FORMAT> "format of the part lists, bricklink saves wanted lists in .xml format")
+        .required(false)
+        .value_parser(["rebrickable", "bricklink"])
+        .default_value("rebrickable");
+    let minifigs = arg!(--minifigs <MODE> "count minifigs as their parts, as whole units or both")
+        .required(false)
+        .value_parser(["parts", "whole", "both"])
+        .default_value("parts");
+    // resolution of the saved charts
+    let chart = [
+        arg!(--size <SIZE> "width and height of saved charts in pixels, like 1920x1080")
+            .required(false)
+            .value_parser(parse_size)
+            .default_value("1920x1080"),
+        arg!(--dpi <DPI> "dots per inch of saved charts, scales fonts and lines")
+            .required(false)
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .default_value("96"),
+    ];
+    let any_color =
+        arg!(--"any-color" "use the same part in another color for missing parts").required(false);
+
+    Command::new("brickstats")
         .version("0.1")
         .author("Sebastian Seiler <sebastian.seiler@posteo.de>")
         .about("A tool for generating lego related diagrams and visualizations.")
-        .arg(arg!(-s --set <VALUE>).required(false))
-        .arg(arg!(-o --output <VALUE>).required(false))
-        .arg(arg!(-f --file <VALUE>).required(false))
-        .arg(arg!(-w - -wordcloud).required(false))
-        .get_matches();
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            arg!(-d --dump <DIR> "directory with the rebrickable csv database dumps (offline mode)")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--refresh "fetch everything again instead of using the cache")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--offline "only use cached api responses, never fetch")
+                .required(false)
+                .global(true)
+                .conflicts_with("refresh"),
+        )
+        .arg(
+            arg!(--"cache-ttl" <DAYS> "days after which cached api responses are fetched again")
+                .required(false)
+                .global(true)
+                .value_parser(clap::value_parser!(u64).range(..=MAX_CACHE_TTL_DAYS)),
+        )
+        .arg(
+            arg!(--"api-url" <URL> "base url of the rebrickable api or a mirror of it")
+                .required(false)
+                .global(true)
+                .default_value(DEFAULT_BASE_URL),
+        )
+        .arg(
+            arg!(--spares "include the spare parts of sets and part lists")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--record <DIR> "record all api responses to a fixture directory")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            arg!(--replay <DIR> "answer all api requests from a recorded fixture directory")
+                .required(false)
+                .global(true)
+                .conflicts_with("record"),
+        )
+        .subcommand(
+            Command::new("histogram")
+                .about("Histogram of the parts per category, colored like the parts")
+                .args(&inputs)
+                .group(inputs_group.clone())
+                .arg(merge.clone())
+                .arg(minifigs.clone())
+                .arg(
+                    arg!(-o --output <FORMAT> "save as images/{name}_histogram.png or .svg instead of showing it in gnuplot")
+                        .required(false)
+                        .value_parser(["png", "svg"]),
+                )
+                .args(&chart),
+        )
+        .subcommand(
+            Command::new("wordcloud")
+                .about("Wordcloud of the part names, saved as images/{name}_wordcloud.png")
+                .args(&inputs)
+                .group(inputs_group.clone())
+                .arg(merge.clone()),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Number of parts, colors and categories and the average part year")
+                .args(&inputs)
+                .group(inputs_group.clone())
+                .arg(merge.clone())
+                .arg(minifigs.clone()),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Parts only in the first or second inventory and quantity differences")
+                .args(&inputs)
+                .group(inputs_group.clone())
+                .arg(
+                    arg!(--csv <FILE> "also save the differences in .csv format").required(false),
+                )
+                .arg(
+                    arg!(--histogram "also show a histogram of the added and removed parts in gnuplot")
+                        .required(false),
+                )
+                .arg(
+                    arg!(-o --output <FORMAT> "save the histogram as images/{a}_vs_{b}_histogram.png or .svg instead of showing it")
+                        .required(false)
+                        .value_parser(["png", "svg"])
+                        .requires("histogram"),
+                )
+                .args(&chart),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Save the inventories as part lists in rebrickable .csv format")
+                .args(&inputs)
+                .group(inputs_group.clone())
+                .arg(merge)
+                .arg(minifigs)
+                .arg(
+                    arg!(-o --output <DIR> "directory for the {name}.csv or {name}.xml files")
+                        .required(false)
+                        .default_value("exports"),
+                )
+                .arg(format.clone()),
+        )
+        .subcommand(
+            Command::new("collection")
+                .about("What a collection of owned parts and sets can build")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("check")
+                        .about("How much of each set the collection covers and which parts are missing")
+                        .args(&inputs)
+                        .group(inputs_group)
+                        .args(&collection)
+                        .group(collection_group.clone())
+                        .arg(any_color.clone())
+                        .arg(
+                            arg!(--equivalents "use alternates, other molds and unprinted parts for missing parts")
+                                .required(false),
+                        )
+                        .arg(
+                            arg!(--missing <DIR> "save the missing parts as {DIR}/{name}_missing.csv or .xml")
+                                .required(false),
+                        )
+                        .arg(format),
+                )
+                .subcommand(
+                    Command::new("rank")
+                        .about("Sets ranked by how much of them the collection covers")
+                        .args(&collection)
+                        .group(collection_group)
+                        .arg(any_color)
+                        .arg(
+                            arg!(--theme <THEME> "only sets of this theme id or name, including its sub-themes")
+                                .required(false),
+                        )
+                        .arg(
+                            arg!(--year <YEARS> "only sets of this year or range of years like 1990-1999")
+                                .required(false)
+                                .value_parser(parse_years),
+                        )
+                        .arg(
+                            arg!(--top <N> "number of sets to list")
+                                .required(false)
+                                .default_value("20")
+                                .value_parser(clap::value_parser!(usize)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("user")
+                .about("Access the collections of your rebrickable account")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("login")
+                        .about("Log in and save the user token to secrets/user_token.txt, asks for the password")
+                        .arg(arg!(<USERNAME> "username or email address of your rebrickable account")),
+                )
+                .subcommand(Command::new("lists").about("Set lists and part lists of your account")),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Inspect or clear the cache of api responses")
+                .subcommand_required(true)
+                .subcommand(Command::new("info").about("Number and size of the cached entries"))
+                .subcommand(
+                    Command::new("clear")
+                        .about("Remove cached entries")
+                        .arg(arg!([ENDPOINT] "only clear this endpoint, e.g. parts")),
+                ),
+        )
+}
 
-    create_directories();
+// save or show a histogram for every input
+fn histogram(matches: &ArgMatches) {
+    let database = open_database(matches);
+    for loader in loaders(matches) {
+        let (inventory, catalogue) = load(database.as_ref(), loader.as_ref(), matches);
+
+        // prepare data for plot, whole minifigs get a bin of their own
+        let mut dataset = prepare_dataset(&inventory, &catalogue);
+        let minifigs = load_minifigs(database.as_ref(), loader.as_ref(), matches);
+        if !minifigs.is_empty() {
+            let quantity = minifigs.iter().map(|minifig| minifig.quantity).sum();
+            dataset = dataset.with_bin("Minifigs".to_string(), quantity, MINIFIG_RGB.to_string());
+        }
+        // e.g. only spares or minifigs, which are left out
+        if dataset.is_empty() {
+            println!(
+                "Warning: {} has no parts to plot, skipping the histogram",
+                loader.title()
+            );
+            continue;
+        }
+        output_histogram(
+            &dataset,
+            matches,
+            format!("images/{}", loader.name()),
+            format!("Parts of {}", loader.title()),
+        );
+    }
+}
 
-    // if api token file is missing, print warning and return
-    if !Path::new("secrets/api_token.txt").exists() {
-        println!("Warning: api token file \"secrets/api_token.txt\" is missing");
-        return;
-    }
-    // read api token from file "../secrets/api_token.txt"
-    let api_token =
-        read_to_string("secrets/api_token.txt").expect("Error reading api token from file");
-    let rebrickable = Rebrickable::new(api_token);
-
-    let output_file_prefix;
-    let title;
-
-    // check set and file parameters
-    let inventory = match matches.get_one::<String>("set") {
-        Some(set) => {
-            if matches.get_one::<String>("file").is_some() {
-                // print warning that file parameter is ignored
-                println!("Warning: file parameter is ignored");
+// show the histogram in gnuplot or save it as {prefix}_histogram.png or .svg without gnuplot,
+// the svg is also saved instead if gnuplot is not installed
+fn output_histogram(dataset: &Dataset, matches: &ArgMatches, prefix: String, title: String) {
+    let format = match matches.get_one::<String>("output") {
+        Some(format) => format.as_str(),
+        None if gnuplot_available() => {
+            if let Err(e) = dataset.output(None, title) {
+                println!("Error: {}", e);
+                std::process::exit(1);
             }
+            return;
+        }
+        None => {
+            println!(
+                "Warning: gnuplot is not installed, saving the histogram as {}_histogram.svg instead",
+                prefix
+            );
+            "svg"
+        }
+    };
+    let (width, height) = *matches.get_one::<(u32, u32)>("size").unwrap();
+    let size = ChartSize {
+        width,
+        height,
+        dpi: *matches.get_one::<u32>("dpi").unwrap() as f32,
+    };
+    let path = format!("{}_histogram.{}", prefix, format);
+    let saved = match format {
+        "png" => dataset.save_png(&path, &title, &size),
+        _ => dataset.save_svg(&path, &title, &size),
+    };
+    exit_on_error(saved.map_err(|source| Error::Io { path, source }));
+}
 
-            // unify set_num_raw to format "12345-1"
-            let set_num = if set.contains('-') {
-                set.to_owned()
-            } else {
-                format!("{}-1", set)
-            };
+// parse a chart size like 1920x1080, at most MAX_CHART_SIDE pixels per side
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("expected width and height like 1920x1080, got {}", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let (width, height): (u32, u32) = match (width.trim().parse(), height.trim().parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(invalid()),
+    };
+    if width > MAX_CHART_SIDE || height > MAX_CHART_SIDE {
+        return Err(format!(
+            "{} is too large, width and height can be at most {}",
+            size, MAX_CHART_SIDE
+        ));
+    }
+    Ok((width, height))
+}
+
+// save a wordcloud for every input
+fn wordclouds(matches: &ArgMatches) {
+    let database = open_database(matches);
+    for loader in loaders(matches) {
+        let (inventory, catalogue) = load(database.as_ref(), loader.as_ref(), matches);
+        let formatted_inventory = formatted_inventory(&inventory, &catalogue);
+        let file_name = format!("images/{}_wordcloud.png", loader.name());
+        wordcloud(formatted_inventory, &file_name).expect("failed to write wordcloud text file");
+    }
+}
+
+// print a summary of every input
+fn summaries(matches: &ArgMatches) {
+    let database = open_database(matches);
+    for loader in loaders(matches) {
+        let (inventory, catalogue) = load(database.as_ref(), loader.as_ref(), matches);
+        let summary = stats::summary(&inventory, &catalogue);
+        println!("{}", loader.title());
+        println!("  parts:             {}", summary.parts);
+        if matches.get_flag("spares") {
+            println!("  spare parts:       {}", summary.spare_parts);
+        }
+        println!("  unique parts:      {}", summary.unique_parts);
+        println!("  lots:              {}", summary.lots);
+        println!("  colors:            {}", summary.colors);
+        println!("  categories:        {}", summary.categories);
+        println!("  average part year: {:.1}", summary.average_part_year);
+        if minifig_mode(matches) != MinifigMode::Parts {
+            let minifigs = load_minifigs(database.as_ref(), loader.as_ref(), matches);
+            let quantity: i32 = minifigs.iter().map(|minifig| minifig.quantity).sum();
+            println!("  minifigs:          {}", quantity);
+            for minifig in &minifigs {
+                println!(
+                    "    {}x {} {}",
+                    minifig.quantity, minifig.fig_num, minifig.name
+                );
+            }
+        }
 
-            output_file_prefix = match matches.get_one::<String>("output") {
-                // if output parameter is set to png, save as png
-                Some(output) if output == "png" => Some(format!("images/{set_num}")),
-                // if other output parameter is set, print warning
-                Some(output) => {
-                    println!("Warning: output parameter \"{}\" is not supported", output);
-                    None
-                }
-                // if no output parameter is set, show dataset
-                None => None,
+        // which contained set, e.g. of a bundle, contributed how many parts
+        let subsets = exit_on_error(loader.subsets(database.as_ref()));
+        if !subsets.is_empty() {
+            println!("  contained sets:");
+        }
+        for subset in &subsets {
+            let subset_loader = SetInventory::new(&subset.set_num);
+            let parts = subset_loader.load(database.as_ref(), &load_options(matches));
+            let parts: i32 = match parts {
+                Ok(inventory) => inventory
+                    .iter()
+                    .map(|inventory_part| inventory_part.quantity)
+                    .sum(),
+                Err(Error::NotFound { .. }) | Err(Error::InvalidSetNumber(_)) => 0,
+                Err(e) => exit_on_error(Err(e)),
             };
+            println!(
+                "    {}x {} {}: {} parts",
+                subset.quantity,
+                subset.set_num,
+                subset.name,
+                parts * subset.quantity
+            );
+        }
+    }
+}
 
-            title = format!("Parts of Set {set_num}");
+// differences between exactly two inputs as table, csv and histogram
+fn compare(matches: &ArgMatches) {
+    let loaders = loaders(matches);
+    let [a, b] = match <[Box<dyn InventoryLoader>; 2]>::try_from(loaders) {
+        Ok(loaders) => loaders,
+        Err(loaders) => {
+            println!(
+                "Error: compare needs exactly two sets or files, got {}",
+                loaders.len()
+            );
+            std::process::exit(1);
+        }
+    };
 
-            // download set inventory
-            let inventory = rebrickable.inventory(&set_num);
-            if inventory.is_empty() {
-                println!("Error: inventory for set {} does not exist (yet)", set_num);
-                return;
-            }
-            inventory
+    let database = open_database(matches);
+    let inventory_a = load_inventory(database.as_ref(), a.as_ref(), matches);
+    let inventory_b = load_inventory(database.as_ref(), b.as_ref(), matches);
+    let both = [inventory_a.as_slice(), inventory_b.as_slice()].concat();
+    let catalogue = exit_on_error(Catalogue::load(database.as_ref(), &both));
+    let differences = stats::compare(&inventory_a, &inventory_b);
+
+    let row = |difference: &Difference| stats::difference_row(difference, &catalogue);
+    let sections = stats::difference_sections(&differences);
+    let sections = [
+        (format!("Only in {}", a.title()), sections.only_a),
+        (format!("Only in {}", b.title()), sections.only_b),
+        ("Different quantities".to_string(), sections.changed),
+    ];
+    for (title, section) in sections {
+        let rows: Vec<Vec<String>> = section.into_iter().map(row).collect();
+        println!("{} ({} lots)", title, rows.len());
+        if !rows.is_empty() {
+            print_table(&stats::DIFFERENCE_COLUMNS, &rows);
         }
-        None => {
-            if let Some(file) = matches.get_one::<String>("file") {
-                let file_path = Path::new(&file);
-                if !file_path.exists() {
-                    println!("Error: file \"{}\" does not exist", file);
-                    return;
-                }
-
-                let file_name = file_path
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .split('.')
-                    .next()
-                    .unwrap();
-
-                output_file_prefix = match matches.get_one::<String>("output") {
-                    // if output parameter is set to png, save as png
-                    Some(output) if output == "png" => Some(format!("images/{file_name}")),
-                    // if other output parameter is set, print warning
-                    Some(output) => {
-                        println!("Warning: output parameter \"{}\" is not supported", output);
-                        None
-                    }
-                    // if no output parameter is set, show dataset
-                    None => None,
-                };
-
-                title = format!("Parts of {file_name}");
-
-                // read dataset from file
-                inventory_from_file(file)
-            } else {
-                // print error that neither set nor file parameter is set
-                println!("Error: neither set nor file parameter is set");
-                return;
-            }
+        println!();
+    }
+
+    if let Some(path) = matches.get_one::<String>("csv") {
+        let rows: Vec<Vec<String>> = differences.iter().map(row).collect();
+        exit_on_error(write_csv(path, &stats::DIFFERENCE_COLUMNS, &rows));
+        println!("Saved {} differences to {}", rows.len(), path);
+    }
+
+    if matches.get_flag("histogram") && differences.is_empty() {
+        println!("No differences, skipping the histogram");
+    } else if matches.get_flag("histogram") {
+        let dataset = prepare_comparison_dataset(&differences, &catalogue);
+        output_histogram(
+            &dataset,
+            matches,
+            format!("images/{}_vs_{}", a.name(), b.name()),
+            format!(
+                "Parts added and removed from {} to {}",
+                a.title(),
+                b.title()
+            ),
+        );
+    }
+}
+
+// print rows as left aligned columns
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
         }
+    }
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
     };
+    println!("{}", format_row(header.to_vec()));
+    for row in rows {
+        println!(
+            "{}",
+            format_row(row.iter().map(|cell| cell.as_str()).collect())
+        );
+    }
+}
 
-    // fetch part, category and color details from rebrickable
-    let colors = rebrickable.all_colors();
-    let categories = rebrickable.all_categories();
-    let part_details = rebrickable.part_details(&inventory);
+fn write_csv(path: &str, header: &[&str], rows: &[Vec<String>]) -> Result<(), Error> {
+    let csv_error = |source| Error::Csv {
+        path: path.to_string(),
+        source,
+    };
+    let mut writer = csv::Writer::from_path(path).map_err(csv_error)?;
+    writer.write_record(header).map_err(csv_error)?;
+    for row in rows {
+        writer.write_record(row).map_err(csv_error)?;
+    }
+    writer.flush().map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })
+}
 
-    // if wordcloud parameter is set, create wordcloud
-    if matches.get_flag("wordcloud") {
-        // check if output is set to png
-        if output_file_prefix.is_none() {
-            println!("Warning: wordcloud is only created if output parameter is set to png");
-        } else {
-            // create formatted inventory
-            let formatted_inventory = formatted_inventory(&inventory, &part_details, &colors);
+// write every input as a part list to the output directory
+fn export(matches: &ArgMatches) {
+    let database = open_database(matches);
+    let directory = matches.get_one::<String>("output").unwrap();
+    exit_on_error(fs::create_dir_all(directory).map_err(|source| Error::Io {
+        path: directory.clone(),
+        source,
+    }));
+    for loader in loaders(matches) {
+        let (inventory, catalogue) = load(database.as_ref(), loader.as_ref(), matches);
+        let path = save_part_list(
+            matches,
+            &inventory,
+            &catalogue,
+            &format!("{}/{}", directory, loader.name()),
+        );
+        println!("Exported {} parts to {}", inventory.len(), path);
 
-            // create filename
-            // either use set_num or input file name
-            let file_name = format!("{}_wordcloud.png", output_file_prefix.as_ref().unwrap());
+        let minifigs = load_minifigs(database.as_ref(), loader.as_ref(), matches);
+        if !minifigs.is_empty() {
+            let path = format!("{}/{}_minifigs.csv", directory, loader.name());
+            let rows: Vec<Vec<String>> = minifigs
+                .iter()
+                .map(|minifig| {
+                    vec![
+                        minifig.fig_num.clone(),
+                        minifig.name.clone(),
+                        minifig.quantity.to_string(),
+                    ]
+                })
+                .collect();
+            exit_on_error(write_csv(&path, &["Minifig", "Name", "Quantity"], &rows));
+            println!("Exported {} minifigs to {}", minifigs.len(), path);
+        }
+    }
+}
 
-            wordcloud(formatted_inventory, &file_name)
-                .expect("failed to write wordcloud text file");
+// save a part list in the format parameter's format, returns the path with extension
+fn save_part_list(
+    matches: &ArgMatches,
+    inventory: &[InventoryPart],
+    catalogue: &Catalogue,
+    path_without_extension: &str,
+) -> String {
+    match matches
+        .get_one::<String>("format")
+        .map(|format| format.as_str())
+    {
+        Some("bricklink") => {
+            let path = format!("{}.xml", path_without_extension);
+            exit_on_error(inventory_to_bricklink_file(inventory, catalogue, &path));
+            path
+        }
+        _ => {
+            let path = format!("{}.csv", path_without_extension);
+            exit_on_error(inventory_to_file(inventory, &path));
+            path
         }
     }
+}
+
+fn collection(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("check", matches)) => check(matches),
+        Some(("rank", matches)) => rank(matches),
+        _ => unreachable!("a collection subcommand is required"),
+    }
+}
 
-    // prepare data for plot
-    let dataset = prepare_dataset(inventory, part_details, categories, colors);
-    dataset.output(output_file_prefix, title);
+// coverage of every input by the collection, with the missing and substituted parts
+fn check(matches: &ArgMatches) {
+    let database = open_database(matches);
+    let collection = Collection::new(&load_collection(database.as_ref(), matches));
+    let substitutes = Substitutes {
+        colors: matches.get_flag("any-color"),
+        equivalent_parts: matches.get_flag("equivalents"),
+    };
+    let missing_directory = matches.get_one::<String>("missing");
+    if let Some(directory) = missing_directory {
+        exit_on_error(fs::create_dir_all(directory).map_err(|source| Error::Io {
+            path: directory.clone(),
+            source,
+        }));
+    }
 
-    // remove temp files
-    fs::remove_dir_all("temp/").expect("failed to remove temp files");
+    for loader in loaders(matches) {
+        let (inventory, catalogue) = load(database.as_ref(), loader.as_ref(), matches);
+        let buildability = collection::check(&inventory, &collection, &catalogue, substitutes);
+        let color_name = |color_id: i32| {
+            catalogue
+                .color(color_id)
+                .map(|color| color.name.clone())
+                .unwrap_or_else(|| color_id.to_string())
+        };
+        let missing_parts: i32 = buildability
+            .missing
+            .iter()
+            .map(|inventory_part| inventory_part.quantity)
+            .sum();
+        let substituted_parts: i32 = buildability
+            .substitutions
+            .iter()
+            .map(|substitution| substitution.quantity)
+            .sum();
+
+        println!("{}", loader.title());
+        println!("  completion:  {:.1}%", buildability.completion());
+        println!(
+            "  parts:       {} of {}",
+            buildability.covered, buildability.required
+        );
+        println!(
+            "  missing:     {} parts in {} lots",
+            missing_parts,
+            buildability.missing.len()
+        );
+        println!("  substituted: {} parts", substituted_parts);
+        println!();
+
+        if !buildability.missing.is_empty() {
+            let rows: Vec<Vec<String>> = buildability
+                .missing
+                .iter()
+                .map(|inventory_part| {
+                    vec![
+                        inventory_part.part_num.clone(),
+                        catalogue
+                            .part(&inventory_part.part_num)
+                            .map(|part| part.name.clone())
+                            .unwrap_or_default(),
+                        color_name(inventory_part.color_id),
+                        inventory_part.quantity.to_string(),
+                    ]
+                })
+                .collect();
+            println!("Missing parts");
+            print_table(&["Part", "Name", "Color", "Quantity"], &rows);
+            println!();
+        }
+        if !buildability.substitutions.is_empty() {
+            let rows: Vec<Vec<String>> = buildability
+                .substitutions
+                .iter()
+                .map(|substitution| {
+                    vec![
+                        substitution.part_num.clone(),
+                        color_name(substitution.color_id),
+                        substitution.quantity.to_string(),
+                        substitution.used_part_num.clone(),
+                        color_name(substitution.used_color_id),
+                    ]
+                })
+                .collect();
+            println!("Substituted parts");
+            print_table(
+                &["Part", "Color", "Quantity", "Used part", "Used color"],
+                &rows,
+            );
+            println!();
+        }
+
+        if let Some(directory) = missing_directory {
+            let path = save_part_list(
+                matches,
+                &buildability.missing,
+                &catalogue,
+                &format!("{}/{}_missing", directory, loader.name()),
+            );
+            println!(
+                "Saved {} missing lots to {}",
+                buildability.missing.len(),
+                path
+            );
+        }
+    }
+}
+
+// the top sets by coverage, optionally of a theme and years only
+fn rank(matches: &ArgMatches) {
+    let database = open_database(matches);
+    let collection = load_collection(database.as_ref(), matches);
+    let themes = exit_on_error(database.all_themes());
+    let filter = SetFilter {
+        theme: matches.get_one::<String>("theme").cloned(),
+        years: matches.get_one::<(i32, i32)>("year").copied(),
+    };
+    let sets = exit_on_error(collection::filter_sets(
+        exit_on_error(database.all_sets()),
+        &themes,
+        &filter,
+    ));
+    println!("Ranking {} sets", sets.len());
+    // the api has no bulk endpoint for inventories, every set not cached yet is a request
+    if matches.get_one::<String>("dump").is_none() && sets.len() > MAX_API_RANK_SETS {
+        println!(
+            "Warning: getting the inventories of {} sets from the api takes up to {} minutes, \
+             use --dump with the rebrickable database dumps instead",
+            sets.len(),
+            sets.len() / 60 + 1
+        );
+    }
+
+    let set_nums: Vec<String> = sets.iter().map(|set| set.set_num.clone()).collect();
+    let inventories = exit_on_error(database.inventories(&set_nums));
+    let substitutes = Substitutes {
+        colors: matches.get_flag("any-color"),
+        equivalent_parts: false,
+    };
+    let ranking = collection::rank(
+        sets,
+        &inventories,
+        &collection,
+        &Catalogue::default(),
+        substitutes,
+    );
+
+    let theme_names: HashMap<i32, &str> = themes
+        .iter()
+        .map(|theme| (theme.id, theme.name.as_str()))
+        .collect();
+    let top = *matches.get_one::<usize>("top").unwrap();
+    let rows: Vec<Vec<String>> = ranking
+        .iter()
+        .take(top)
+        .enumerate()
+        .map(|(index, ranked)| {
+            vec![
+                (index + 1).to_string(),
+                ranked.set.set_num.clone(),
+                ranked.set.name.clone(),
+                ranked.set.year.to_string(),
+                theme_names
+                    .get(&ranked.set.theme_id)
+                    .map(|name| name.to_string())
+                    .unwrap_or_default(),
+                ranked.buildability.required.to_string(),
+                ranked.missing_parts().to_string(),
+                ranked.buildability.missing.len().to_string(),
+                format!("{:.1}%", ranked.buildability.completion()),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "Rank",
+            "Set",
+            "Name",
+            "Year",
+            "Theme",
+            "Parts",
+            "Missing",
+            "Missing lots",
+            "Completion",
+        ],
+        &rows,
+    );
+}
+
+// "1990" or "1990-1999" as inclusive range of years
+fn parse_years(years: &str) -> Result<(i32, i32), String> {
+    let (from, to) = years.split_once('-').unwrap_or((years, years));
+    let parse = |year: &str| {
+        year.trim()
+            .parse::<i32>()
+            .map_err(|_| format!("invalid year {}", year))
+    };
+    let (from, to) = (parse(from)?, parse(to)?);
+    if from > to {
+        return Err(format!("{} is after {}", from, to));
+    }
+    Ok((from, to))
+}
+
+// all owned sets and files as a single inventory
+fn load_collection(database: &dyn Database, matches: &ArgMatches) -> Vec<InventoryPart> {
+    let mut collection = MergedInventory::new();
+    for (count, loader) in inputs(matches, "owned-set", "owned-file", "owned-user") {
+        collection.add(count, loader);
+    }
+    load_inventory(database, &collection, matches)
+}
+
+fn user(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("login", matches)) => login(matches),
+        Some(("lists", matches)) => user_lists(matches),
+        _ => unreachable!("a user subcommand is required"),
+    }
+}
+
+// log in with the password typed without echo, or piped to stdin, and save the user token
+fn login(matches: &ArgMatches) {
+    let username = matches.get_one::<String>("USERNAME").unwrap();
+    let prompt = format!("Password for {}: ", username);
+    let password = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(prompt)
+    } else {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password).map(|_| password)
+    };
+    let password = exit_on_error(password.map_err(|source| Error::Io {
+        path: "stdin".to_string(),
+        source,
+    }));
+    let user_token = exit_on_error(open_rebrickable(matches).login(username, password.trim()));
+    exit_on_error(
+        fs::write(USER_TOKEN_FILE, user_token).map_err(|source| Error::Io {
+            path: USER_TOKEN_FILE.to_string(),
+            source,
+        }),
+    );
+    println!(
+        "Logged in as {}, saved the user token to {}",
+        username, USER_TOKEN_FILE
+    );
+}
+
+// print the set lists and part lists of the user
+fn user_lists(matches: &ArgMatches) {
+    let database = open_database(matches);
+    let sections = [
+        ("Set lists", "Sets", database.user_set_lists()),
+        ("Part lists", "Parts", database.user_part_lists()),
+    ];
+    for (title, size, lists) in sections {
+        let rows: Vec<Vec<String>> = exit_on_error(lists)
+            .into_iter()
+            .map(|list| vec![list.id.to_string(), list.name, list.size.to_string()])
+            .collect();
+        println!("{} ({})", title, rows.len());
+        if !rows.is_empty() {
+            print_table(&["Id", "Name", size], &rows);
+        }
+        println!();
+    }
+}
+
+fn cache(matches: &ArgMatches) {
+    let cache = open_cache(matches);
+    match matches.subcommand() {
+        Some(("info", _)) => {
+            let usage = exit_on_error(cache.usage());
+            for endpoint in &usage {
+                println!(
+                    "{}: {} entries, {} KiB",
+                    endpoint.endpoint,
+                    endpoint.entries,
+                    endpoint.bytes / 1024
+                );
+            }
+            let entries: usize = usage.iter().map(|endpoint| endpoint.entries).sum();
+            let bytes: u64 = usage.iter().map(|endpoint| endpoint.bytes).sum();
+            println!("total: {} entries, {} KiB", entries, bytes / 1024);
+        }
+        Some(("clear", matches)) => {
+            let endpoint = matches.get_one::<String>("ENDPOINT");
+            let removed = exit_on_error(cache.clear(endpoint.map(|endpoint| endpoint.as_str())));
+            println!("Removed {} cached entries", removed);
+        }
+        _ => unreachable!("a cache subcommand is required"),
+    }
+}
+
+// sets and files in the order given
+// with the merge parameter all of them are combined into a single inventory
+fn loaders(matches: &ArgMatches) -> Vec<Box<dyn InventoryLoader>> {
+    let loaders = inputs(matches, "set", "file", "user");
+
+    // compare has no merge parameter
+    if let Ok(Some(true)) = matches.try_get_one::<bool>("merge") {
+        let mut merged = MergedInventory::new();
+        for (count, loader) in loaders {
+            merged.add(count, loader);
+        }
+        return vec![Box::new(merged)];
+    }
+    loaders
+        .into_iter()
+        .map(|(count, loader)| match count {
+            1 => loader,
+            _ => Box::new(MergedInventory::new().with(count, loader)),
+        })
+        .collect()
+}
+
+// counted sets and files and user collections of the given parameters in the order given
+fn inputs(
+    matches: &ArgMatches,
+    set_id: &str,
+    file_id: &str,
+    user_id: &str,
+) -> Vec<(i32, Box<dyn InventoryLoader>)> {
+    let mut inputs: Vec<(usize, i32, Box<dyn InventoryLoader>)> = Vec::new();
+    if let (Some(sets), Some(indices)) = (
+        matches.get_many::<String>(set_id),
+        matches.indices_of(set_id),
+    ) {
+        for (set, index) in sets.zip(indices) {
+            let (count, set) = split_count(set);
+            inputs.push((index, count, Box::new(SetInventory::new(set))));
+        }
+    }
+    if let (Some(files), Some(indices)) = (
+        matches.get_many::<String>(file_id),
+        matches.indices_of(file_id),
+    ) {
+        for (file, index) in files.zip(indices) {
+            let (count, file) = split_count(file);
+            inputs.push((index, count, Box::new(FileInventory::new(file))));
+        }
+    }
+    if let (Some(collections), Some(indices)) = (
+        matches.get_many::<UserInventory>(user_id),
+        matches.indices_of(user_id),
+    ) {
+        for (collection, index) in collections.zip(indices) {
+            inputs.push((index, 1, Box::new(collection.clone())));
+        }
+    }
+    inputs.sort_by_key(|(index, _, _)| *index);
+    inputs
+        .into_iter()
+        .map(|(_, count, loader)| (count, loader))
+        .collect()
+}
+
+// split "3x 10698-1" into count and input, inputs without count are counted once
+// the space keeps file names like "10x10 plates.csv" intact
+fn split_count(input: &str) -> (i32, &str) {
+    if let Some((count, rest)) = input.split_once("x ") {
+        if let Ok(count) = count.trim().parse::<i32>() {
+            let rest = rest.trim_start();
+            if count > 0 && !rest.is_empty() {
+                return (count, rest);
+            }
+        }
+    }
+    (1, input)
+}
+
+// download set inventory or read it from file and fetch part, category and color details
+fn load(
+    database: &dyn Database,
+    loader: &dyn InventoryLoader,
+    matches: &ArgMatches,
+) -> (Vec<InventoryPart>, Catalogue) {
+    let inventory = load_inventory(database, loader, matches);
+    let catalogue = exit_on_error(Catalogue::load(database, &inventory));
+    (inventory, catalogue)
+}
+
+fn load_inventory(
+    database: &dyn Database,
+    loader: &dyn InventoryLoader,
+    matches: &ArgMatches,
+) -> Vec<InventoryPart> {
+    exit_on_error(loader.load(database, &load_options(matches)))
+}
+
+// spare parts are only kept with the spares parameter,
+// the parts of minifigs only unless they are counted as whole units
+fn load_options(matches: &ArgMatches) -> LoadOptions {
+    LoadOptions {
+        spares: matches.get_flag("spares"),
+        minifigs: minifig_mode(matches),
+    }
+}
+
+// whole minifigs of sets, none if they are only counted as parts
+fn load_minifigs(
+    database: &dyn Database,
+    loader: &dyn InventoryLoader,
+    matches: &ArgMatches,
+) -> Vec<InventoryMinifig> {
+    match minifig_mode(matches) {
+        MinifigMode::Parts => Vec::new(),
+        MinifigMode::Whole | MinifigMode::Both => exit_on_error(loader.minifigs(database)),
+    }
+}
+
+// parts, whole or both, commands without minifigs parameter count their parts
+fn minifig_mode(matches: &ArgMatches) -> MinifigMode {
+    match matches.try_get_one::<String>("minifigs") {
+        Ok(Some(mode)) if mode == "whole" => MinifigMode::Whole,
+        Ok(Some(mode)) if mode == "both" => MinifigMode::Both,
+        _ => MinifigMode::Parts,
+    }
+}
+
+fn open_cache(matches: &ArgMatches) -> Cache {
+    let cache_mode = if matches.get_one::<String>("record").is_some()
+        || matches.get_one::<String>("replay").is_some()
+    {
+        // every request must reach the fixtures, and replayed data must not end up in the cache
+        CacheMode::Disabled
+    } else if matches.get_flag("refresh") {
+        CacheMode::Refresh
+    } else if matches.get_flag("offline") {
+        CacheMode::Offline
+    } else {
+        CacheMode::Normal
+    };
+    let ttl_days = matches
+        .get_one::<u64>("cache-ttl")
+        .copied()
+        .unwrap_or(DEFAULT_TTL_DAYS);
+    Cache::new(
+        CACHE_DIRECTORY,
+        Duration::from_secs(ttl_days * 24 * 60 * 60),
+        cache_mode,
+    )
+}
+
+fn open_database(matches: &ArgMatches) -> Box<dyn Database> {
+    // answer all queries from the local database dump
+    if let Some(directory) = matches.get_one::<String>("dump") {
+        return Box::new(exit_on_error(RebrickableDump::new(directory)));
+    }
+    Box::new(open_rebrickable(matches))
+}
+
+fn open_rebrickable(matches: &ArgMatches) -> Rebrickable {
+    let record = matches.get_one::<String>("record");
+    let replay = matches.get_one::<String>("replay");
+    let cache = open_cache(matches);
+
+    // if api token file is missing, print error and exit
+    // (not needed if everything comes from the cache)
+    let api_token = if Path::new("secrets/api_token.txt").exists() {
+        // read api token from file "../secrets/api_token.txt"
+        read_to_string("secrets/api_token.txt")
+            .expect("Error reading api token from file")
+            .trim()
+            .to_string()
+    } else if matches.get_flag("offline") || replay.is_some() {
+        String::new()
+    } else {
+        println!("Error: api token file \"secrets/api_token.txt\" is missing");
+        std::process::exit(1);
+    };
+    let api_url = matches.get_one::<String>("api-url").unwrap();
+    let transport: Box<dyn Transport> = match (record, replay) {
+        (Some(directory), _) => Box::new(exit_on_error(RecordingTransport::new(
+            Box::new(ReqwestTransport::new()),
+            directory,
+        ))),
+        (_, Some(directory)) => Box::new(exit_on_error(ReplayTransport::new(directory))),
+        _ => Box::new(ReqwestTransport::new()),
+    };
+    let rebrickable = Rebrickable::new(api_token, cache)
+        .with_base_url(api_url)
+        .with_transport(transport);
+
+    // the user token is only needed for user collections, see user login
+    match read_to_string(USER_TOKEN_FILE) {
+        Ok(user_token) => rebrickable.with_user_token(user_token.trim().to_string()),
+        Err(_) => rebrickable,
+    }
 }
 
 // create "secrets", "images" and "data" directories if they don't exist
@@ -155,3 +1116,47 @@ fn create_directories() {
         }
     }
 }
+
+// print the error and exit with its distinct exit code
+fn exit_on_error<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_split_from_inputs() {
+        assert_eq!(split_count("3x 10698-1"), (3, "10698-1"));
+        assert_eq!(split_count("2x  parts.csv"), (2, "parts.csv"));
+        assert_eq!(split_count("10698-1"), (1, "10698-1"));
+        // file names with an x followed by a space are kept intact
+        assert_eq!(split_count("10x10 plates.csv"), (1, "10x10 plates.csv"));
+        assert_eq!(split_count("box 1.csv"), (1, "box 1.csv"));
+        assert_eq!(split_count("0x 10698-1"), (1, "0x 10698-1"));
+    }
+
+    #[test]
+    fn sizes_are_positive_and_bounded() {
+        assert_eq!(parse_size("1920x1080"), Ok((1920, 1080)));
+        assert_eq!(parse_size("16384x16384"), Ok((16384, 16384)));
+        assert!(parse_size("0x1080").is_err());
+        assert!(parse_size("1920").is_err());
+        assert!(parse_size("16385x1080").is_err());
+        assert!(parse_size("1920x4000000000").is_err());
+    }
+
+    #[test]
+    fn years_are_single_years_or_ranges() {
+        assert_eq!(parse_years("1990"), Ok((1990, 1990)));
+        assert_eq!(parse_years("1990-1999"), Ok((1990, 1999)));
+        assert_eq!(parse_years(" 1990 - 1999 "), Ok((1990, 1999)));
+        assert!(parse_years("1999-1990").is_err());
+        assert!(parse_years("nineties").is_err());
+        assert!(parse_years("1990-").is_err());
+    }
+}
@@ -1,5 +1,9 @@
+//! Charts and images generated from inventories.
+
 mod plot;
+mod raster;
+mod svg;
 mod wordcloud;
 
-pub use plot::Dataset;
+pub use plot::{gnuplot_available, ChartSize, Dataset};
 pub use wordcloud::{wordcloud, InventoryEntry};
@@ -1,9 +1,10 @@
+use super::{raster, svg};
 use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::io::{BufWriter, Write};
+use std::fs;
+use std::io::{self, BufWriter, Write};
 use std::process::{Command, Stdio};
 
-// convert to png with something similar to >> set terminal pngcairo enhanced font \"Times New Roman,12.0\" size 1500,1100
 pub const DEFAULT_CONFIG: &str = "
 set lmargin screen 0.05
 set rmargin screen 0.95
@@ -15,15 +16,39 @@ set boxwidth 0.9 relative
 set ytics auto
 set xtics rotate by -45 scale 0
 set grid ytics
+set xzeroaxis
 set title font \"Helvetica,20\"
 ";
 
+/// Size of svg and png charts in pixels, and the dpi that fonts and lines are scaled to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartSize {
+    pub width: u32,
+    pub height: u32,
+    pub dpi: f32,
+}
+
+impl Default for ChartSize {
+    // the size of the png images gnuplot used to save
+    fn default() -> ChartSize {
+        ChartSize {
+            width: 1920,
+            height: 1080,
+            dpi: 96.0,
+        }
+    }
+}
+
+/// Histogram data: one label per bin, one row of bin values per stacked color.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Dataset {
     set_num: String,
     labels: Vec<String>,
     data: Vec<Vec<i32>>,
     color_rgbs: Vec<String>,
+    // rows drawn hatched, e.g. spare parts
+    #[serde(default)]
+    hatched: Vec<bool>,
 }
 
 impl Dataset {
@@ -38,19 +63,87 @@ impl Dataset {
             labels,
             data,
             color_rgbs,
+            hatched: Vec::new(),
+        }
+    }
+
+    /// Draws the rows flagged true hatched instead of solid, e.g. spare parts.
+    pub fn with_hatched_rows(mut self, hatched: Vec<bool>) -> Dataset {
+        self.hatched = hatched;
+        self
+    }
+
+    /// Adds a bin of a single solid row after the others, e.g. the minifigs of a set.
+    pub fn with_bin(mut self, label: String, quantity: i32, color_rgb: String) -> Dataset {
+        for row in &mut self.data {
+            row.push(0);
         }
+        let mut row = vec![0; self.labels.len()];
+        row.push(quantity);
+        self.labels.push(label);
+        self.data.push(row);
+        self.color_rgbs.push(color_rgb);
+        self
+    }
+
+    /// Whether there is nothing to plot, e.g. no parts or no differences.
+    pub fn is_empty(&self) -> bool {
+        self.data.iter().flatten().all(|value| *value == 0)
     }
 
-    pub fn output(&self, output: Option<String>, title: String) {
-        Gnuplot::output(
-            self.labels.clone(),
-            self.data.clone(),
-            self.color_rgbs.clone(),
+    /// Shows the histogram with gnuplot, or saves it as `{output}_histogram.png` of the
+    /// default size if an output prefix is given. Empty histograms are not shown.
+    pub fn output(&self, output: Option<String>, title: String) -> Result<()> {
+        match output {
+            Some(prefix) => {
+                let path = format!("{}_histogram.png", prefix);
+                self.save_png(&path, &title, &ChartSize::default())
+                    .map_err(|e| Error::msg(format!("failed to save {}: {}", path, e)))
+            }
+            // gnuplot needs at least one row
+            None if self.is_empty() => Ok(()),
+            None => Gnuplot::output(
+                self.labels.clone(),
+                self.data.clone(),
+                self.color_rgbs.clone(),
+                self.hatched.clone(),
+                title,
+            ),
+        }
+    }
+
+    /// Draws the histogram as svg without gnuplot.
+    pub fn to_svg(&self, title: &str, size: &ChartSize) -> String {
+        svg::histogram(
+            &self.labels,
+            &self.data,
+            &self.color_rgbs,
+            &self.hatched,
             title,
-            output,
+            size,
         )
-        .unwrap();
     }
+
+    /// Saves the histogram as svg to `path`, e.g. `images/10698-1_histogram.svg`.
+    pub fn save_svg(&self, path: &str, title: &str, size: &ChartSize) -> io::Result<()> {
+        fs::write(path, self.to_svg(title, size))
+    }
+
+    /// Saves the histogram as png to `path` without gnuplot, e.g. `images/10698-1_histogram.png`.
+    pub fn save_png(&self, path: &str, title: &str, size: &ChartSize) -> io::Result<()> {
+        fs::write(path, raster::svg_to_png(&self.to_svg(title, size), size)?)
+    }
+}
+
+/// Whether gnuplot is installed and available in PATH.
+pub fn gnuplot_available() -> bool {
+    Command::new("gnuplot")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
 }
 
 // gnuplot object
@@ -62,30 +155,38 @@ pub struct Gnuplot {
 }
 
 impl Gnuplot {
-    // create output fn that replaces show and save_png
+    // show the histogram in a gnuplot window, images are drawn without gnuplot
     pub fn output(
         labels: Vec<String>,
         data: Vec<Vec<i32>>,
         colors: Vec<String>,
+        hatched: Vec<bool>,
         title: String,
-        output: Option<String>,
     ) -> Result<()> {
         let (config_string, data_string) =
-            Gnuplot::prepare_plotting(labels, data, colors, output, title);
+            Gnuplot::prepare_plotting(labels, data, colors, hatched, title);
 
         let mut process = Command::new("gnuplot")
             .arg("-p")
             .stdin(Stdio::piped())
             .spawn()
-            .expect("Couldn't spawn gnuplot. Make sure it is installed and available in PATH.");
-        {
+            .map_err(|e| {
+                Error::msg(format!(
+                    "couldn't spawn gnuplot, make sure it is installed and available in PATH: {}",
+                    e
+                ))
+            })?;
+        let written = {
             let mut stdin = BufWriter::new(process.stdin.take().unwrap());
-            writeln!(stdin, "{}", config_string)?;
-            writeln!(stdin, "{}", data_string)?;
-            stdin.flush()?;
-        }
+            writeln!(stdin, "{}", config_string)
+                .and_then(|_| writeln!(stdin, "{}", data_string))
+                .and_then(|_| stdin.flush())
+        };
 
-        match process.wait() {
+        // always wait for gnuplot, even if writing to it failed
+        let status = process.wait();
+        written?;
+        match status {
             Ok(status) => {
                 if status.success() {
                     Ok(())
@@ -101,27 +202,31 @@ impl Gnuplot {
         labels: Vec<String>,
         data: Vec<Vec<i32>>,
         colors: Vec<String>,
-        file_name_prefix: Option<String>,
+        hatched: Vec<bool>,
         title: String,
     ) -> (String, String) {
         let mut color_iter = colors.iter();
+        // hatched rows use a pattern in their color instead of the solid fill
+        let fill = |row: usize| {
+            if hatched.get(row).copied().unwrap_or(false) {
+                " fs pattern 4 border -1"
+            } else {
+                ""
+            }
+        };
         // generate config string
         let mut config_string = format!("{DEFAULT_CONFIG}\nset title \"{title}\"",);
-        if let Some(file_name) = file_name_prefix {
-            config_string.push_str(&format!("\nset output '{}_histogram.png'", file_name));
-            config_string.push_str(
-                "\nset terminal pngcairo enhanced font \"Times New Roman,12.0\" size 1920,1080",
-            );
-        }
 
         config_string.push_str(&format!(
-            "\nplot '-' using 2:xtic(1) with histogram notitle lc rgb \"#{}\",",
-            color_iter.next().expect("didn't get enough colors")
+            "\nplot '-' using 2:xtic(1) with histogram notitle lc rgb \"#{}\"{},",
+            color_iter.next().expect("didn't get enough colors"),
+            fill(0)
         ));
-        for _ in data.iter().skip(1) {
+        for row in 1..data.len() {
             config_string.push_str(&format!(
-                " '-' using 2 with histogram notitle lc rgb \"#{}\",",
-                color_iter.next().expect("didn't get enough colors")
+                " '-' using 2 with histogram notitle lc rgb \"#{}\"{},",
+                color_iter.next().expect("didn't get enough colors"),
+                fill(row)
             ));
         }
         // remove last comma
@@ -148,3 +253,26 @@ impl Gnuplot {
         (config_string, data_string)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_datasets_are_not_plotted() {
+        let empty = Dataset::new("".to_string(), vec![], vec![], vec![]);
+        assert!(empty.is_empty());
+        // would panic in prepare_plotting or spawn gnuplot if it was plotted
+        empty.output(None, "Nothing".to_string()).unwrap();
+
+        let zeros = Dataset::new(
+            "".to_string(),
+            vec!["Bricks".to_string()],
+            vec![vec![0]],
+            vec!["C91A09".to_string()],
+        );
+        assert!(zeros.is_empty());
+        let minifigs = zeros.with_bin("Minifigs".to_string(), 2, "F2CD37".to_string());
+        assert!(!minifigs.is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+//! Charts rasterised to png in-process, without gnuplot.
+use super::plot::ChartSize;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::fontdb::{Database, Family, Query};
+use resvg::usvg::{Options, Tree};
+use std::io;
+
+/// Rasterises an svg chart of `size` to png, with the dpi of `size` stored in the png.
+pub fn svg_to_png(svg: &str, size: &ChartSize) -> io::Result<Vec<u8>> {
+    let mut options = Options {
+        dpi: size.dpi,
+        ..Options::default()
+    };
+    load_fonts(options.fontdb_mut());
+    let tree =
+        Tree::from_str(svg, &options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut pixmap = Pixmap::new(size.width, size.height).ok_or_else(|| {
+        io::Error::other(format!(
+            "chart size {}x{} is too large",
+            size.width, size.height
+        ))
+    })?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+
+    // encoded with the png crate, as tiny-skia does not store the dpi
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, size.width, size.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels_per_meter = (size.dpi / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+// load the installed fonts, the generic serif and sans-serif families default to windows
+// fonts and fall back to any installed font on headless servers without them
+fn load_fonts(fontdb: &mut Database) {
+    fontdb.load_system_fonts();
+    let fallback = match fontdb.faces().find_map(|face| face.families.first()) {
+        Some((family, _)) => family.clone(),
+        None => {
+            println!("Warning: no fonts are installed, the chart is drawn without text");
+            return;
+        }
+    };
+    let installed = |fontdb: &Database, family| {
+        let families = [family];
+        let query = Query {
+            families: &families,
+            ..Query::default()
+        };
+        fontdb.query(&query).is_some()
+    };
+    if !installed(fontdb, Family::Serif) {
+        fontdb.set_serif_family(fallback.clone());
+    }
+    if !installed(fontdb, Family::SansSerif) {
+        fontdb.set_sans_serif_family(fallback);
+    }
+}
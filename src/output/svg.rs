@@ -0,0 +1,193 @@
+//! Histograms drawn as svg without gnuplot, laid out like the gnuplot charts.
+use super::plot::ChartSize;
+
+// margins of the gnuplot charts relative to the size, see plot::DEFAULT_CONFIG
+const LEFT: f64 = 0.05;
+const RIGHT: f64 = 0.95;
+const TOP: f64 = 0.1;
+// the rest below the plot is left for the rotated category labels
+const BOTTOM: f64 = 0.72;
+// boxwidth 0.9 relative
+const BOX_WIDTH: f64 = 0.9;
+// gnuplot fonts, followed by similar fonts common on linux
+const FONT: &str = "Times New Roman, Liberation Serif, DejaVu Serif, serif";
+const FONT_POINTS: f64 = 12.0;
+const TITLE_FONT: &str = "Helvetica, Arial, Liberation Sans, DejaVu Sans, sans-serif";
+const TITLE_FONT_POINTS: f64 = 20.0;
+// about as many y tics as gnuplot picks with ytics auto
+const Y_TICS: f64 = 8.0;
+
+/// Draws a row-stacked histogram as svg: one bin per label, one stacked row of values per
+/// color. Positive values are stacked above the zero axis, negative ones below it. Rows
+/// flagged in `hatched` are drawn with diagonal lines in their color instead of solid.
+pub fn histogram(
+    labels: &[String],
+    data: &[Vec<i32>],
+    colors: &[String],
+    hatched: &[bool],
+    title: &str,
+    size: &ChartSize,
+) -> String {
+    let width = size.width as f64;
+    let height = size.height as f64;
+    let (left, right) = (LEFT * width, RIGHT * width);
+    let (top, bottom) = (TOP * height, BOTTOM * height);
+    // fonts are sized in points and lines are one pixel wide at 96 dpi
+    let font_size = FONT_POINTS * size.dpi as f64 / 72.0;
+    let title_font_size = TITLE_FONT_POINTS * size.dpi as f64 / 72.0;
+    let line = size.dpi as f64 / 96.0;
+
+    // highest and lowest stack of all bins
+    let mut max = 0;
+    let mut min = 0;
+    for bin in 0..labels.len() {
+        let values = data.iter().map(|row| row.get(bin).copied().unwrap_or(0));
+        max = max.max(values.clone().filter(|value| *value > 0).sum());
+        min = min.min(values.filter(|value| *value < 0).sum());
+    }
+    let step = tic_step((max - min) as f64);
+    let y_min = (min as f64 / step).floor() * step;
+    let y_max = match (max as f64 / step).ceil() * step {
+        // an empty histogram still gets a y axis
+        y_max if y_max <= y_min => y_min + step,
+        y_max => y_max,
+    };
+    let y = |value: f64| bottom - (value - y_min) / (y_max - y_min) * (bottom - top);
+    let bin_width = (right - left) / labels.len().max(1) as f64;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" font-family=\"{FONT}\" font-size=\"{font_size:.1}\">\n"
+    );
+    svg.push_str(&format!(
+        "<rect width=\"{width}\" height=\"{height}\" fill=\"white\"/>\n"
+    ));
+
+    // diagonal lines in the color of the row, like fill pattern 4 of gnuplot
+    let hatch = 8.0 * line;
+    svg.push_str("<defs>\n");
+    for (row, color) in colors.iter().enumerate() {
+        if hatched.get(row).copied().unwrap_or(false) {
+            svg.push_str(&format!(
+                "<pattern id=\"hatch{row}\" patternUnits=\"userSpaceOnUse\" width=\"{hatch:.1}\" height=\"{hatch:.1}\"><rect width=\"{hatch:.1}\" height=\"{hatch:.1}\" fill=\"white\"/><path d=\"M{:.1},{:.1} L{:.1},{:.1} M0,{hatch:.1} L{hatch:.1},0 M{:.1},{:.1} L{:.1},{:.1}\" stroke=\"#{color}\" stroke-width=\"{:.1}\"/></pattern>\n",
+                -hatch / 4.0,
+                hatch / 4.0,
+                hatch / 4.0,
+                -hatch / 4.0,
+                hatch * 3.0 / 4.0,
+                hatch * 5.0 / 4.0,
+                hatch * 5.0 / 4.0,
+                hatch * 3.0 / 4.0,
+                2.0 * line
+            ));
+        }
+    }
+    svg.push_str("</defs>\n");
+
+    svg.push_str(&format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" font-family=\"{TITLE_FONT}\" font-size=\"{title_font_size:.1}\" text-anchor=\"middle\">{}</text>\n",
+        width / 2.0,
+        top / 2.0 + title_font_size / 3.0,
+        escape(title)
+    ));
+
+    // y tics with grid lines
+    let mut tic = y_min;
+    while tic <= y_max + step / 2.0 {
+        svg.push_str(&format!(
+            "<line x1=\"{left:.1}\" y1=\"{0:.1}\" x2=\"{right:.1}\" y2=\"{0:.1}\" stroke=\"#a0a0a0\" stroke-width=\"{line:.1}\" stroke-dasharray=\"{1:.1},{1:.1}\"/>\n",
+            y(tic),
+            4.0 * line
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+            left - font_size / 2.0,
+            y(tic) + font_size / 3.0,
+            tic
+        ));
+        tic += step;
+    }
+
+    // stacked boxes, bordered in black like "fill solid border -1"
+    for bin in 0..labels.len() {
+        let x = left + (bin as f64 + (1.0 - BOX_WIDTH) / 2.0) * bin_width;
+        let mut above = 0.0;
+        let mut below = 0.0;
+        for (row, values) in data.iter().enumerate() {
+            let value = values.get(bin).copied().unwrap_or(0) as f64;
+            let (from, to) = if value > 0.0 {
+                above += value;
+                (above - value, above)
+            } else if value < 0.0 {
+                below += value;
+                (below - value, below)
+            } else {
+                continue;
+            };
+            let fill = if hatched.get(row).copied().unwrap_or(false) {
+                format!("url(#hatch{})", row)
+            } else {
+                format!(
+                    "#{}",
+                    colors.get(row).map(String::as_str).unwrap_or("000000")
+                )
+            };
+            svg.push_str(&format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"black\" stroke-width=\"{line:.1}\"/>\n",
+                x,
+                y(from.max(to)),
+                bin_width * BOX_WIDTH,
+                (y(from) - y(to)).abs(),
+                fill
+            ));
+        }
+    }
+
+    // zero axis and border of the plot
+    svg.push_str(&format!(
+        "<line x1=\"{left:.1}\" y1=\"{0:.1}\" x2=\"{right:.1}\" y2=\"{0:.1}\" stroke=\"black\" stroke-width=\"{line:.1}\"/>\n",
+        y(0.0)
+    ));
+    svg.push_str(&format!(
+        "<rect x=\"{left:.1}\" y=\"{top:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"black\" stroke-width=\"{line:.1}\"/>\n",
+        right - left,
+        bottom - top
+    ));
+
+    // category labels below the bins, rotated by 45 degrees and ending at their bin so that
+    // long labels run down to the left into the space below the plot instead of off the right
+    for (bin, label) in labels.iter().enumerate() {
+        let x = left + (bin as f64 + 0.5) * bin_width;
+        let y = bottom + font_size;
+        svg.push_str(&format!(
+            "<text x=\"{x:.1}\" y=\"{y:.1}\" text-anchor=\"end\" transform=\"rotate(-45 {x:.1} {y:.1})\">{}</text>\n",
+            escape(label)
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// distance of the y tics: 1, 2 or 5 times a power of ten
+fn tic_step(range: f64) -> f64 {
+    if range <= 0.0 {
+        return 1.0;
+    }
+    let rough = range / Y_TICS;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    // quantities are whole parts
+    step.max(1.0)
+}
+
+// escape text for svg
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
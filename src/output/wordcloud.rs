@@ -1,4 +1,5 @@
 use anyhow::Result;
+use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
@@ -7,6 +8,16 @@ use std::process::Command;
 
 const TEMP_TEXT_FILE: &str = "temp/wordcloud.txt";
 
+lazy_static! {
+    static ref WRITTEN_NUMBER: Regex =
+        Regex::new(r"(?i)\s(one|two|three|four|five|six|seven|eight|nine|ten)\s").unwrap();
+    static ref SPACE_AFTER_NUMBER: Regex = Regex::new(r"\s(\d)\s").unwrap();
+    static ref PREPOSITION: Regex =
+        Regex::new(r"(?i)\s(with|on|of|for|in|to|from|and|or)\s").unwrap();
+    static ref NO: Regex = Regex::new(r"(?i)((\s|_)no)\s").unwrap();
+}
+
+/// One line of the wordcloud input: quantity, color and part name.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InventoryEntry {
     color: String,
@@ -41,8 +52,7 @@ fn text_file(inventory_entries: Vec<InventoryEntry>) -> Result<()> {
         let mut part_name_formatted = entry.part_name.replace(" x ", "x");
 
         // replace written numbers with numbers ignoring case using regex
-        let re = Regex::new(r"(?i)\s(one|two|three|four|five|six|seven|eight|nine|ten)\s").unwrap();
-        part_name_formatted = re
+        part_name_formatted = WRITTEN_NUMBER
             .replace_all(&part_name_formatted, |caps: &regex::Captures| {
                 let number = caps.get(1).unwrap().as_str();
                 println!("parsing number: {}", number);
@@ -51,24 +61,21 @@ fn text_file(inventory_entries: Vec<InventoryEntry>) -> Result<()> {
             .to_string();
 
         // replace space after number with _
-        let re = Regex::new(r"\s(\d)\s").unwrap();
-        part_name_formatted = re
+        part_name_formatted = SPACE_AFTER_NUMBER
             .replace_all(&part_name_formatted, |caps: &regex::Captures| {
                 format!(" {}_", caps.get(1).unwrap().as_str())
             })
             .to_string();
 
         // add _ after each preposition, ignoring case
-        let re = Regex::new(r"(?i)\s(with|on|of|for|in|to|from|and|or)\s").unwrap();
-        part_name_formatted = re
+        part_name_formatted = PREPOSITION
             .replace_all(&part_name_formatted, |caps: &regex::Captures| {
                 format!(" {}_", caps.get(1).unwrap().as_str().to_lowercase())
             })
             .to_string();
 
         // add _ after each "_no " or " no ", ignoring case
-        let re = Regex::new(r"(?i)((\s|_)no)\s").unwrap();
-        part_name_formatted = re
+        part_name_formatted = NO
             .replace_all(&part_name_formatted, |caps: &regex::Captures| {
                 format!("{}_", caps.get(2).unwrap().as_str().to_lowercase())
             })
@@ -81,7 +88,7 @@ fn text_file(inventory_entries: Vec<InventoryEntry>) -> Result<()> {
     Ok(())
 }
 
-// create wordcloud from text file
+/// Creates a wordcloud image at `output_path` with wordcloud_cli.
 pub fn wordcloud(inventory_entries: Vec<InventoryEntry>, output_path: &str) -> Result<()> {
     text_file(inventory_entries)?;
 
@@ -1,17 +1,218 @@
-use crate::input::{inventory_part, part_details};
+use crate::input::{Catalogue, InventoryPart};
+use std::collections::{BTreeMap, HashSet};
 
-// calculate average age of parts in inventory
-#[allow(dead_code)]
-pub fn average_part_year(inventory_parts: &[inventory_part], part_details: &[part_details]) -> f32 {
+/// Key figures of an inventory.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    /// Number of parts, counting every piece.
+    pub parts: i32,
+    /// Number of spare parts among them.
+    pub spare_parts: i32,
+    /// Number of distinct parts, regardless of color.
+    pub unique_parts: usize,
+    /// Number of distinct part and color combinations.
+    pub lots: usize,
+    pub colors: usize,
+    pub categories: usize,
+    pub average_part_year: f32,
+}
+
+/// Summarizes the inventory, parts without details count towards no category.
+pub fn summary(inventory_parts: &[InventoryPart], catalogue: &Catalogue) -> Summary {
+    let mut unique_parts = HashSet::new();
+    let mut lots = HashSet::new();
+    let mut colors = HashSet::new();
+    let mut categories = HashSet::new();
+    for inventory_part in inventory_parts {
+        unique_parts.insert(inventory_part.part_num.as_str());
+        lots.insert((inventory_part.part_num.as_str(), inventory_part.color_id));
+        colors.insert(inventory_part.color_id);
+        if let Some(part) = catalogue.part(&inventory_part.part_num) {
+            categories.insert(part.part_cat_id);
+        }
+    }
+    Summary {
+        parts: inventory_parts
+            .iter()
+            .map(|inventory_part| inventory_part.quantity)
+            .sum(),
+        spare_parts: inventory_parts
+            .iter()
+            .filter(|inventory_part| inventory_part.is_spare)
+            .map(|inventory_part| inventory_part.quantity)
+            .sum(),
+        unique_parts: unique_parts.len(),
+        lots: lots.len(),
+        colors: colors.len(),
+        categories: categories.len(),
+        average_part_year: average_part_year(inventory_parts, catalogue),
+    }
+}
+
+/// Average year in which the parts of the inventory were first released, weighted by quantity.
+///
+/// Parts without details or release year are left out.
+pub fn average_part_year(inventory_parts: &[InventoryPart], catalogue: &Catalogue) -> f32 {
     let mut average_year = 0.0;
     let mut part_count = 0;
 
-    // zip inventory_parts and part_details
-    let tuples = inventory_parts.iter().zip(part_details.iter());
-
-    for (inventory_part, part_details) in tuples {
-        average_year += part_details.year_from() as f32 * inventory_part.quantity() as f32;
-        part_count += inventory_part.quantity();
+    for inventory_part in inventory_parts {
+        if let Some(part) = catalogue
+            .part(&inventory_part.part_num)
+            .filter(|part| part.year_from > 0)
+        {
+            average_year += part.year_from as f32 * inventory_part.quantity as f32;
+            part_count += inventory_part.quantity;
+        }
     }
     average_year / part_count as f32
 }
+
+/// Quantities of a part in one color in two inventories.
+#[derive(Debug, Clone)]
+pub struct Difference {
+    pub part_num: String,
+    pub color_id: i32,
+    pub quantity_a: i32,
+    pub quantity_b: i32,
+}
+
+impl Difference {
+    /// Parts added (positive) or removed (negative) from a to b.
+    pub fn change(&self) -> i32 {
+        self.quantity_b - self.quantity_a
+    }
+}
+
+/// Parts and colors whose quantities differ between inventories a and b, sorted by part and color.
+/// Parts only in a have a `quantity_b` of zero and vice versa.
+pub fn compare(a: &[InventoryPart], b: &[InventoryPart]) -> Vec<Difference> {
+    let mut quantities: BTreeMap<(&str, i32), (i32, i32)> = BTreeMap::new();
+    for inventory_part in a {
+        let key = (inventory_part.part_num.as_str(), inventory_part.color_id);
+        quantities.entry(key).or_default().0 += inventory_part.quantity;
+    }
+    for inventory_part in b {
+        let key = (inventory_part.part_num.as_str(), inventory_part.color_id);
+        quantities.entry(key).or_default().1 += inventory_part.quantity;
+    }
+    quantities
+        .into_iter()
+        .filter(|(_, (quantity_a, quantity_b))| quantity_a != quantity_b)
+        .map(
+            |((part_num, color_id), (quantity_a, quantity_b))| Difference {
+                part_num: part_num.to_string(),
+                color_id,
+                quantity_a,
+                quantity_b,
+            },
+        )
+        .collect()
+}
+
+/// Column titles of [`difference_row`].
+pub const DIFFERENCE_COLUMNS: [&str; 7] = ["Part", "Name", "Color", "Category", "A", "B", "Change"];
+
+/// A difference as table cells: part, name, color, category, both quantities and the change.
+/// Details missing from the catalogue are left empty, unknown colors show their id.
+pub fn difference_row(difference: &Difference, catalogue: &Catalogue) -> Vec<String> {
+    let part = catalogue.part(&difference.part_num);
+    vec![
+        difference.part_num.clone(),
+        part.map(|part| part.name.clone()).unwrap_or_default(),
+        catalogue
+            .color(difference.color_id)
+            .map(|color| color.name.clone())
+            .unwrap_or_else(|| difference.color_id.to_string()),
+        part.and_then(|part| catalogue.category(part.part_cat_id))
+            .map(|category| category.name.clone())
+            .unwrap_or_default(),
+        difference.quantity_a.to_string(),
+        difference.quantity_b.to_string(),
+        format!("{:+}", difference.change()),
+    ]
+}
+
+/// Differences by where the lots are found.
+#[derive(Debug, Default)]
+pub struct DifferenceSections<'a> {
+    pub only_a: Vec<&'a Difference>,
+    pub only_b: Vec<&'a Difference>,
+    /// Lots in both inventories with different quantities.
+    pub changed: Vec<&'a Difference>,
+}
+
+/// Splits the differences into lots only in a, only in b and in both, keeping their order.
+pub fn difference_sections(differences: &[Difference]) -> DifferenceSections<'_> {
+    let mut sections = DifferenceSections::default();
+    for difference in differences {
+        if difference.quantity_b == 0 {
+            sections.only_a.push(difference);
+        } else if difference.quantity_a == 0 {
+            sections.only_b.push(difference);
+        } else {
+            sections.changed.push(difference);
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differences_are_sorted_and_sum_over_spares() {
+        let a = [
+            InventoryPart::new("3002".to_string(), 1, 2, false),
+            InventoryPart::new("3001".to_string(), 4, 1, false),
+            InventoryPart::new("3001".to_string(), 4, 1, true),
+        ];
+        let b = [
+            InventoryPart::new("3001".to_string(), 4, 2, false),
+            InventoryPart::new("3003".to_string(), 0, 3, false),
+        ];
+        let differences = compare(&a, &b);
+        let differences: Vec<(&str, i32, i32)> = differences
+            .iter()
+            .map(|difference| {
+                (
+                    difference.part_num.as_str(),
+                    difference.color_id,
+                    difference.change(),
+                )
+            })
+            .collect();
+        // 3001 in red is the same in both, counting the spare
+        assert_eq!(differences, vec![("3002", 1, -2), ("3003", 0, 3)]);
+    }
+
+    #[test]
+    fn differences_are_split_into_sections_and_rows() {
+        let a = [
+            InventoryPart::new("3001".to_string(), 4, 1, false),
+            InventoryPart::new("3002".to_string(), 4, 2, false),
+        ];
+        let b = [
+            InventoryPart::new("3002".to_string(), 4, 5, false),
+            InventoryPart::new("3003".to_string(), 4, 1, false),
+        ];
+        let differences = compare(&a, &b);
+        let sections = difference_sections(&differences);
+        assert_eq!(sections.only_a[0].part_num, "3001");
+        assert_eq!(sections.only_b[0].part_num, "3003");
+        assert_eq!(sections.changed[0].part_num, "3002");
+
+        // parts and colors without details only show what the difference knows
+        let row = difference_row(sections.changed[0], &Catalogue::default());
+        assert_eq!(row.len(), DIFFERENCE_COLUMNS.len());
+        assert_eq!(row, vec!["3002", "", "4", "", "2", "5", "+3"]);
+    }
+
+    #[test]
+    fn equal_inventories_have_no_differences() {
+        let a = [InventoryPart::new("3001".to_string(), 4, 1, false)];
+        assert!(compare(&a, &a).is_empty());
+        assert!(compare(&[], &[]).is_empty());
+    }
+}